@@ -0,0 +1,214 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Sleeping is only precise to about a millisecond, the rest is spun.
+const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+
+/// When transactions are issued.
+///
+/// Written as `closed`, `constant:<ops_per_sec>` or `poisson:<ops_per_sec>`
+/// on the command line and in scenarios.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Arrival {
+    /// The next transaction starts when the previous one returned.
+    Closed,
+    /// Transactions are issued at a fixed interval, so the table receives
+    /// `ops_per_sec` operations per second.
+    Constant { ops_per_sec: f64 },
+    /// Transactions arrive as a Poisson process at the same rate.
+    Poisson { ops_per_sec: f64 },
+}
+
+impl Arrival {
+    pub fn name(self) -> &'static str {
+        match self {
+            Arrival::Closed => "closed",
+            Arrival::Constant { .. } => "constant",
+            Arrival::Poisson { .. } => "poisson",
+        }
+    }
+
+    /// Operations per second offered to the backend, `None` for closed loops.
+    pub fn offered_load(self) -> Option<f64> {
+        match self {
+            Arrival::Closed => None,
+            Arrival::Constant { ops_per_sec } | Arrival::Poisson { ops_per_sec } => {
+                Some(ops_per_sec)
+            }
+        }
+    }
+
+    /// The intended start of every transaction relative to the start of the
+    /// run, `None` for closed loops. Poisson gaps are drawn from their own
+    /// stream of `seed`, so they do not change the operations.
+    pub fn schedule(
+        self,
+        seed: u64,
+        transaction_size: usize,
+        transaction_count: usize,
+    ) -> Option<Vec<Duration>> {
+        let interval = transaction_size as f64 / self.offered_load()?;
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(1);
+
+        let mut offset = 0.0;
+        let schedule = (0..transaction_count)
+            .map(|_| {
+                let start = Duration::from_secs_f64(offset);
+                offset += match self {
+                    Arrival::Poisson { .. } => -(1.0 - rng.gen::<f64>()).ln() * interval,
+                    _ => interval,
+                };
+                start
+            })
+            .collect();
+        Some(schedule)
+    }
+}
+
+/// The open-loop arrivals, for sweeping their rate.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ArrivalProcess {
+    Constant,
+    Poisson,
+}
+
+impl ArrivalProcess {
+    pub fn at(self, ops_per_sec: f64) -> Arrival {
+        match self {
+            ArrivalProcess::Constant => Arrival::Constant { ops_per_sec },
+            ArrivalProcess::Poisson => Arrival::Poisson { ops_per_sec },
+        }
+    }
+}
+
+/// Blocks until `deadline`, returning right away when it already passed.
+pub fn wait_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        let remaining = deadline - now;
+        if remaining > SPIN_THRESHOLD {
+            std::thread::sleep(remaining - SPIN_THRESHOLD);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+impl FromStr for Arrival {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, rate) = match value.split_once(':') {
+            Some((name, rate)) => (name, Some(rate)),
+            None => (value, None),
+        };
+        let ops_per_sec = || -> Result<f64, String> {
+            let rate = rate.ok_or_else(|| format!("Arrival {} needs a rate in operations per second", value))?;
+            match rate.parse::<f64>() {
+                Ok(rate) if rate > 0.0 => Ok(rate),
+                _ => Err(format!("Invalid rate {} in arrival {}", rate, value)),
+            }
+        };
+
+        match name {
+            "closed" if rate.is_none() => Ok(Arrival::Closed),
+            "constant" => Ok(Arrival::Constant {
+                ops_per_sec: ops_per_sec()?,
+            }),
+            "poisson" => Ok(Arrival::Poisson {
+                ops_per_sec: ops_per_sec()?,
+            }),
+            _ => Err(format!("Unknown arrival {}", value)),
+        }
+    }
+}
+
+impl TryFrom<String> for Arrival {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Arrival> for String {
+    fn from(value: Arrival) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for Arrival {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offered_load() {
+            Some(ops_per_sec) => write!(f, "{}:{}", self.name(), ops_per_sec),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_loops_have_no_schedule() {
+        assert_eq!(Arrival::Closed.schedule(1, 10, 5), None);
+        assert_eq!(Arrival::Closed.offered_load(), None);
+    }
+
+    #[test]
+    fn constant_arrivals_are_evenly_spaced() {
+        // 10 operations per transaction at 1000 per second is one every 10 ms.
+        let schedule = Arrival::Constant {
+            ops_per_sec: 1000.0,
+        }
+        .schedule(1, 10, 4)
+        .unwrap();
+        let expected = (0..4)
+            .map(|i| Duration::from_millis(10 * i))
+            .collect::<Vec<_>>();
+        assert_eq!(schedule, expected);
+    }
+
+    #[test]
+    fn poisson_arrivals_keep_the_rate_on_average() {
+        let arrival = Arrival::Poisson {
+            ops_per_sec: 1000.0,
+        };
+        let schedule = arrival.schedule(1, 10, 10000).unwrap();
+        assert_eq!(schedule, arrival.schedule(1, 10, 10000).unwrap());
+        assert_ne!(schedule, arrival.schedule(2, 10, 10000).unwrap());
+
+        let gaps = schedule
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>();
+        let mean = gaps.iter().sum::<Duration>().as_secs_f64() / gaps.len() as f64;
+        assert!((mean - 0.01).abs() < 0.0005, "mean gap {}", mean);
+        // Gaps are exponential, nearly a tenth are under a tenth of the mean.
+        let short = gaps.iter().filter(|gap| gap.as_secs_f64() < 0.001).count();
+        assert!(short > 500, "{} short gaps", short);
+    }
+
+    #[test]
+    fn rates_have_to_be_positive() {
+        assert_eq!(
+            "poisson:2.5".parse(),
+            Ok(Arrival::Poisson { ops_per_sec: 2.5 })
+        );
+        for value in ["poisson", "poisson:0", "constant:-1", "closed:10"] {
+            assert!(value.parse::<Arrival>().is_err(), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::workload::{Key, Operation, Value};
+
+use tenaciouszebra_dashmap::database::{
+    Database as DashMapDatabase, Query as DashMapQuery, Table as DashMapTable,
+    TableResponse as DashMapTableResponse, TableTransaction as DashMapTableTransaction,
+};
+use tenaciouszebra_file_store::database::{
+    Database as FileStoreDatabase, Query as FileStoreQuery, Table as FileStoreTable,
+    TableResponse as FileStoreTableResponse, TableTransaction as FileStoreTableTransaction,
+};
+use tenaciouszebra_okaywal::database::{
+    Database as OkayWalDatabase, Query as OkayWalQuery, Table as OkayWalTable,
+    TableResponse as OkayWalTableResponse, TableTransaction as OkayWalTableTransaction,
+};
+use tenaciouszebra_pickledb::database::{
+    Database as PickleDbDatabase, Query as PickleDbQuery, Table as PickleDbTable,
+    TableResponse as PickleDbTableResponse, TableTransaction as PickleDbTableTransaction,
+};
+use tenaciouszebra_rocksdb_wal::database::{
+    Database as RocksDbWalDatabase, Query as RocksDbWalQuery, Table as RocksDbWalTable,
+    TableResponse as RocksDbWalTableResponse, TableTransaction as RocksDbWalTableTransaction,
+};
+use tenaciouszebra_single_rocksdb::database::{
+    Database as SingleRocksDbDatabase, Query as SingleRocksDbQuery, Table as SingleRocksDbTable,
+    TableResponse as SingleRocksDbTableResponse, TableTransaction as SingleRocksDbTableTransaction,
+};
+
+/// Directory used by the backends that persist to disk.
+pub const DATA_PATH: &str = "test";
+
+/// Directory the file-store backups are written to.
+pub const BACKUP_PATH: &str = "./backup";
+
+/// Name of the table every benchmark runs against.
+pub const TABLE_NAME: &str = "test";
+
+/// The backends selectable from the command line and in scenario files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    RocksdbWal,
+    NoBackup,
+    FileBackup,
+    Dashmap,
+    Okaywal,
+    SingleRocksdb,
+    Pickledb,
+}
+
+/// Evaluates `$body` with `$B` naming the adapter of `$backend`, the one
+/// place mapping a `Backend` to its `StorageBackend`.
+macro_rules! with_backend {
+    ($backend:expr, $B:ident => $body:expr) => {
+        match $backend {
+            $crate::backend::Backend::RocksdbWal => {
+                type $B = $crate::backend::RocksDbWal;
+                $body
+            }
+            $crate::backend::Backend::NoBackup => {
+                type $B = $crate::backend::FileStore;
+                $body
+            }
+            $crate::backend::Backend::FileBackup => {
+                type $B = $crate::backend::FileBackup;
+                $body
+            }
+            $crate::backend::Backend::Dashmap => {
+                type $B = $crate::backend::DashMap;
+                $body
+            }
+            $crate::backend::Backend::Okaywal => {
+                type $B = $crate::backend::OkayWal;
+                $body
+            }
+            $crate::backend::Backend::SingleRocksdb => {
+                type $B = $crate::backend::SingleRocksDb;
+                $body
+            }
+            $crate::backend::Backend::Pickledb => {
+                type $B = $crate::backend::PickleDb;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_backend;
+
+/// Like `with_backend`, with `$B` implementing `Durable`. Panics for the
+/// backends keeping nothing on disk, which the commands needing one do not
+/// accept.
+macro_rules! with_durable_backend {
+    ($backend:expr, $B:ident => $body:expr) => {
+        match $backend {
+            $crate::backend::Backend::RocksdbWal => {
+                type $B = $crate::backend::RocksDbWal;
+                $body
+            }
+            $crate::backend::Backend::FileBackup => {
+                type $B = $crate::backend::FileBackup;
+                $body
+            }
+            $crate::backend::Backend::Okaywal => {
+                type $B = $crate::backend::OkayWal;
+                $body
+            }
+            $crate::backend::Backend::SingleRocksdb => {
+                type $B = $crate::backend::SingleRocksDb;
+                $body
+            }
+            $crate::backend::Backend::Pickledb => {
+                type $B = $crate::backend::PickleDb;
+                $body
+            }
+            backend @ ($crate::backend::Backend::NoBackup | $crate::backend::Backend::Dashmap) => {
+                panic!("Backend {} keeps nothing on disk", backend.name())
+            }
+        }
+    };
+}
+pub(crate) use with_durable_backend;
+
+impl Backend {
+    /// Short name used in log lines and result file names.
+    pub fn name(self) -> &'static str {
+        with_backend!(self, B => B::NAME)
+    }
+
+    /// The tenaciouszebra branch the backend comes from.
+    pub fn branch(self) -> &'static str {
+        match self {
+            Backend::RocksdbWal => "feature/rocksdb-wal",
+            Backend::NoBackup | Backend::FileBackup => "main",
+            Backend::Dashmap => "feature/dashmap",
+            Backend::Okaywal => "feature/add-okaywal",
+            Backend::SingleRocksdb => "feature/single-rocksdb",
+            Backend::Pickledb => "feature/pickel-db",
+        }
+    }
+
+    /// Whether the backend keeps its table on disk, see `Durable`.
+    pub fn is_durable(self) -> bool {
+        !matches!(self, Backend::NoBackup | Backend::Dashmap)
+    }
+}
+
+/// The operations a benchmark needs from a `TableTransaction`, independent
+/// of which tenaciouszebra branch it comes from.
+pub trait Transaction {
+    type Query: Send;
+
+    fn set(&mut self, key: Key, value: Value);
+    fn get(&mut self, key: Key) -> Self::Query;
+    fn remove(&mut self, key: Key);
+}
+
+/// The `TableResponse` of an executed transaction.
+pub trait Response<Query> {
+    /// The value `query` read, `None` if the key was not set.
+    fn value(&self, query: &Query) -> Option<&Value>;
+}
+
+/// A tenaciouszebra branch the benchmarks can run against.
+///
+/// Adding a branch means adding a dependency alias in `Cargo.toml` and one
+/// adapter implementing this trait, plus its `Backend` variant and its arm in
+/// `with_backend`.
+///
+/// Worker threads share one backend, so `execute` only borrows it.
+pub trait StorageBackend: Sync {
+    type Transaction: Transaction + Send;
+    type Response: Response<<Self::Transaction as Transaction>::Query> + Send;
+
+    /// Short name used in log lines and result file names.
+    const NAME: &'static str;
+
+    /// Directory the backend persists to, measured before teardown.
+    const DATA_DIR: Option<&'static str> = None;
+
+    /// Opens a fresh database and creates the empty benchmark table.
+    fn open() -> Self;
+
+    fn new_transaction() -> Self::Transaction;
+
+    fn build_transaction(operations: Vec<Operation>) -> Self::Transaction {
+        Self::build_transaction_with_queries(operations).0
+    }
+
+    /// Like `build_transaction`, also returning the query of every get, in
+    /// order.
+    fn build_transaction_with_queries(
+        operations: Vec<Operation>,
+    ) -> (Self::Transaction, Vec<<Self::Transaction as Transaction>::Query>) {
+        let mut transaction = Self::new_transaction();
+        let mut queries = Vec::new();
+        for operation in operations {
+            match operation {
+                Operation::Set(key, value) => transaction.set(key, value),
+                Operation::Get(key) => queries.push(transaction.get(key)),
+                Operation::Remove(key) => transaction.remove(key),
+            }
+        }
+        (transaction, queries)
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response;
+
+    /// Reads `keys` in a single transaction.
+    fn read(&self, keys: &[Key]) -> Vec<Option<Value>> {
+        let mut transaction = Self::new_transaction();
+        let queries = keys
+            .iter()
+            .map(|key| transaction.get(key.clone()))
+            .collect::<Vec<_>>();
+        let response = self.execute(transaction);
+        queries
+            .iter()
+            .map(|query| response.value(query).cloned())
+            .collect()
+    }
+
+    /// Removes everything the backend left behind.
+    fn teardown(self);
+}
+
+/// A backend that keeps its table on disk across processes.
+pub trait Durable: StorageBackend {
+    /// Opens the table a previous process left in the backend's directory,
+    /// the way a restarted node would.
+    fn reopen() -> Self;
+}
+
+macro_rules! impl_transaction {
+    ($transaction:ty, $response:ty, $query:ty, $key:ident => $get_key:expr) => {
+        impl Transaction for $transaction {
+            type Query = $query;
+
+            fn set(&mut self, key: Key, value: Value) {
+                <$transaction>::set(self, key, value).unwrap();
+            }
+
+            fn get(&mut self, $key: Key) -> Self::Query {
+                <$transaction>::get(self, $get_key).unwrap()
+            }
+
+            fn remove(&mut self, $key: Key) {
+                <$transaction>::remove(self, $get_key).unwrap();
+            }
+        }
+
+        impl Response<$query> for $response {
+            fn value(&self, query: &$query) -> Option<&Value> {
+                self.get(query)
+            }
+        }
+    };
+}
+
+// The rocksdb-wal branch takes keys by value in `get` and `remove`, the others
+// by reference.
+impl_transaction!(RocksDbWalTableTransaction<Key, Value>, RocksDbWalTableResponse<Key, Value>, RocksDbWalQuery, key => key);
+impl_transaction!(FileStoreTableTransaction<Key, Value>, FileStoreTableResponse<Key, Value>, FileStoreQuery, key => &key);
+impl_transaction!(DashMapTableTransaction<Key, Value>, DashMapTableResponse<Key, Value>, DashMapQuery, key => &key);
+impl_transaction!(OkayWalTableTransaction<Key, Value>, OkayWalTableResponse<Key, Value>, OkayWalQuery, key => &key);
+impl_transaction!(SingleRocksDbTableTransaction<Key, Value>, SingleRocksDbTableResponse<Key, Value>, SingleRocksDbQuery, key => &key);
+impl_transaction!(PickleDbTableTransaction<Key, Value>, PickleDbTableResponse<Key, Value>, PickleDbQuery, key => &key);
+
+pub struct RocksDbWal {
+    _db: RocksDbWalDatabase<Key, Value>,
+    table: Arc<RocksDbWalTable<Key, Value>>,
+}
+
+impl StorageBackend for RocksDbWal {
+    type Transaction = RocksDbWalTableTransaction<Key, Value>;
+    type Response = RocksDbWalTableResponse<Key, Value>;
+
+    const NAME: &'static str = "rocksdb_wal";
+    const DATA_DIR: Option<&'static str> = Some(DATA_PATH);
+
+    fn open() -> Self {
+        let db = RocksDbWalDatabase::new(DATA_PATH);
+        let table = db.empty_table(TABLE_NAME);
+        RocksDbWal { _db: db, table }
+    }
+
+    fn new_transaction() -> Self::Transaction {
+        RocksDbWalTableTransaction::new()
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response {
+        self.table.execute(transaction)
+    }
+
+    fn teardown(self) {
+        drop(self);
+        std::fs::remove_dir_all(DATA_PATH).unwrap();
+    }
+}
+
+impl Durable for RocksDbWal {
+    fn reopen() -> Self {
+        let db = RocksDbWalDatabase::new(DATA_PATH);
+        let table = db
+            .get_table(TABLE_NAME)
+            .unwrap_or_else(|| db.empty_table(TABLE_NAME));
+        RocksDbWal { _db: db, table }
+    }
+}
+
+pub struct FileStore {
+    _db: FileStoreDatabase<Key, Value>,
+    table: Arc<FileStoreTable<Key, Value>>,
+}
+
+impl StorageBackend for FileStore {
+    type Transaction = FileStoreTableTransaction<Key, Value>;
+    type Response = FileStoreTableResponse<Key, Value>;
+
+    const NAME: &'static str = "no_backup";
+
+    fn open() -> Self {
+        let db = FileStoreDatabase::new();
+        let table = db.empty_table(TABLE_NAME);
+        FileStore { _db: db, table }
+    }
+
+    fn new_transaction() -> Self::Transaction {
+        FileStoreTableTransaction::new()
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response {
+        self.table.execute(transaction)
+    }
+
+    fn teardown(self) {}
+}
+
+/// The file-store branch with a full backup after every transaction.
+pub struct FileBackup {
+    db: FileStoreDatabase<Key, Value>,
+    table: Arc<FileStoreTable<Key, Value>>,
+}
+
+impl StorageBackend for FileBackup {
+    type Transaction = FileStoreTableTransaction<Key, Value>;
+    type Response = FileStoreTableResponse<Key, Value>;
+
+    const NAME: &'static str = "file_backup";
+    const DATA_DIR: Option<&'static str> = Some(BACKUP_PATH);
+
+    fn open() -> Self {
+        let db = FileStoreDatabase::new();
+        let table = db.empty_table(TABLE_NAME);
+        db.backup(BACKUP_PATH);
+        FileBackup { db, table }
+    }
+
+    fn new_transaction() -> Self::Transaction {
+        FileStoreTableTransaction::new()
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response {
+        let response = self.table.execute(transaction);
+        self.db.backup(BACKUP_PATH);
+        response
+    }
+
+    fn teardown(self) {
+        drop(self);
+        std::fs::remove_dir_all(BACKUP_PATH).unwrap();
+    }
+}
+
+impl Durable for FileBackup {
+    fn reopen() -> Self {
+        let db = FileStoreDatabase::restore(BACKUP_PATH);
+        let table = db.get_table(TABLE_NAME).unwrap();
+        FileBackup { db, table }
+    }
+}
+
+pub struct DashMap {
+    _db: DashMapDatabase<Key, Value>,
+    /// The dashmap branch executes through `&mut Table`, so concurrent
+    /// workers take turns.
+    table: Mutex<DashMapTable<Key, Value>>,
+}
+
+impl StorageBackend for DashMap {
+    type Transaction = DashMapTableTransaction<Key, Value>;
+    type Response = DashMapTableResponse<Key, Value>;
+
+    const NAME: &'static str = "no_backup_dashmap";
+
+    fn open() -> Self {
+        let db = DashMapDatabase::new();
+        let table = Mutex::new(db.empty_table());
+        DashMap { _db: db, table }
+    }
+
+    fn new_transaction() -> Self::Transaction {
+        DashMapTableTransaction::new()
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response {
+        self.table.lock().unwrap().execute(transaction)
+    }
+
+    fn teardown(self) {}
+}
+
+pub struct OkayWal {
+    _db: OkayWalDatabase<Key, Value>,
+    table: Arc<OkayWalTable<Key, Value>>,
+}
+
+impl StorageBackend for OkayWal {
+    type Transaction = OkayWalTableTransaction<Key, Value>;
+    type Response = OkayWalTableResponse<Key, Value>;
+
+    const NAME: &'static str = "okaywal";
+    const DATA_DIR: Option<&'static str> = Some(DATA_PATH);
+
+    fn open() -> Self {
+        let db = OkayWalDatabase::new(DATA_PATH);
+        let table = db.empty_table(TABLE_NAME);
+        OkayWal { _db: db, table }
+    }
+
+    fn new_transaction() -> Self::Transaction {
+        OkayWalTableTransaction::new()
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response {
+        self.table.execute(transaction)
+    }
+
+    fn teardown(self) {
+        drop(self);
+        std::fs::remove_dir_all(DATA_PATH).unwrap();
+    }
+}
+
+impl Durable for OkayWal {
+    fn reopen() -> Self {
+        let db = OkayWalDatabase::new(DATA_PATH);
+        let table = db
+            .get_table(TABLE_NAME)
+            .unwrap_or_else(|| db.empty_table(TABLE_NAME));
+        OkayWal { _db: db, table }
+    }
+}
+
+pub struct SingleRocksDb {
+    _db: SingleRocksDbDatabase<Key, Value>,
+    table: Arc<SingleRocksDbTable<Key, Value>>,
+}
+
+impl StorageBackend for SingleRocksDb {
+    type Transaction = SingleRocksDbTableTransaction<Key, Value>;
+    type Response = SingleRocksDbTableResponse<Key, Value>;
+
+    const NAME: &'static str = "single_rocksdb";
+    const DATA_DIR: Option<&'static str> = Some(DATA_PATH);
+
+    fn open() -> Self {
+        let db = SingleRocksDbDatabase::new(DATA_PATH);
+        let table = db.empty_table(TABLE_NAME);
+        SingleRocksDb { _db: db, table }
+    }
+
+    fn new_transaction() -> Self::Transaction {
+        SingleRocksDbTableTransaction::new()
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response {
+        self.table.execute(transaction)
+    }
+
+    fn teardown(self) {
+        drop(self);
+        std::fs::remove_dir_all(DATA_PATH).unwrap();
+    }
+}
+
+impl Durable for SingleRocksDb {
+    fn reopen() -> Self {
+        let db = SingleRocksDbDatabase::new(DATA_PATH);
+        let table = db
+            .get_table(TABLE_NAME)
+            .unwrap_or_else(|| db.empty_table(TABLE_NAME));
+        SingleRocksDb { _db: db, table }
+    }
+}
+
+pub struct PickleDb {
+    _db: PickleDbDatabase<Key, Value>,
+    table: Arc<PickleDbTable<Key, Value>>,
+}
+
+impl StorageBackend for PickleDb {
+    type Transaction = PickleDbTableTransaction<Key, Value>;
+    type Response = PickleDbTableResponse<Key, Value>;
+
+    const NAME: &'static str = "pickledb";
+    const DATA_DIR: Option<&'static str> = Some(DATA_PATH);
+
+    fn open() -> Self {
+        let db = PickleDbDatabase::new(DATA_PATH);
+        let table = db.empty_table(TABLE_NAME);
+        PickleDb { _db: db, table }
+    }
+
+    fn new_transaction() -> Self::Transaction {
+        PickleDbTableTransaction::new()
+    }
+
+    fn execute(&self, transaction: Self::Transaction) -> Self::Response {
+        self.table.execute(transaction)
+    }
+
+    fn teardown(self) {
+        drop(self);
+        std::fs::remove_dir_all(DATA_PATH).unwrap();
+    }
+}
+
+impl Durable for PickleDb {
+    fn reopen() -> Self {
+        let db = PickleDbDatabase::new(DATA_PATH);
+        let table = db
+            .get_table(TABLE_NAME)
+            .unwrap_or_else(|| db.empty_table(TABLE_NAME));
+        PickleDb { _db: db, table }
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Serialize, Serializer};
+use tenaciouszebra_file_store::database::Database as FileStoreDatabase;
+
+use crate::backend::{Backend, FileStore, StorageBackend, BACKUP_PATH, TABLE_NAME};
+use crate::manifest::{self, unix_ms, Measurement, Recorder, RunManifest};
+use crate::stats::Summary;
+use crate::workload::{bytes_set, Key, Value, Workload, WorkloadConfig};
+
+/// When the file store backs its database up.
+///
+/// Written as `never`, `transactions:<count>`, `interval:<ms>` or
+/// `dirty-bytes:<bytes>` on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(into = "String")]
+pub enum BackupPolicy {
+    Never,
+    /// After every `count`-th transaction.
+    Transactions(usize),
+    /// After the first transaction ending at least `ms` after the last backup.
+    Interval(u64),
+    /// After the transaction that brought the key and value bytes set since
+    /// the last backup to at least `bytes`.
+    DirtyBytes(u64),
+}
+
+impl BackupPolicy {
+    fn due(self, transactions: usize, bytes: u64, since_backup: Duration) -> bool {
+        match self {
+            BackupPolicy::Never => false,
+            BackupPolicy::Transactions(count) => transactions >= count,
+            BackupPolicy::Interval(ms) => since_backup >= Duration::from_millis(ms),
+            BackupPolicy::DirtyBytes(threshold) => bytes >= threshold,
+        }
+    }
+}
+
+impl FromStr for BackupPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "never" {
+            return Ok(BackupPolicy::Never);
+        }
+        let Some((name, parameter)) = value.split_once(':') else {
+            return Err(format!("Backup policy {} needs a parameter", value));
+        };
+        let parameter = match parameter.parse::<u64>() {
+            Ok(parameter) if parameter > 0 => parameter,
+            _ => return Err(format!("Invalid parameter {} in backup policy {}", parameter, value)),
+        };
+        match name {
+            "transactions" => Ok(BackupPolicy::Transactions(parameter as usize)),
+            "interval" => Ok(BackupPolicy::Interval(parameter)),
+            "dirty-bytes" => Ok(BackupPolicy::DirtyBytes(parameter)),
+            _ => Err(format!("Unknown backup policy {}", value)),
+        }
+    }
+}
+
+impl From<BackupPolicy> for String {
+    fn from(value: BackupPolicy) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for BackupPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupPolicy::Never => write!(f, "never"),
+            BackupPolicy::Transactions(count) => write!(f, "transactions:{}", count),
+            BackupPolicy::Interval(ms) => write!(f, "interval:{}", ms),
+            BackupPolicy::DirtyBytes(bytes) => write!(f, "dirty-bytes:{}", bytes),
+        }
+    }
+}
+
+/// One run of the file store under a backup policy.
+#[derive(Serialize)]
+pub struct CadenceRun {
+    #[serde(rename = "duration_ms", serialize_with = "millis")]
+    pub duration: Duration,
+    /// Operations per second, backups included.
+    pub throughput: f64,
+    pub backups: usize,
+    /// Time spent backing up.
+    #[serde(rename = "backup_ms", serialize_with = "millis")]
+    pub backup_time: Duration,
+    /// Most transactions that were committed but not yet backed up at once.
+    pub max_lost_transactions: usize,
+    /// Most key and value bytes that were set but not yet backed up at once.
+    pub max_lost_bytes: u64,
+    /// Longest a committed transaction waited for the backup holding it, or
+    /// for the end of the run.
+    #[serde(rename = "max_loss_window_ms", serialize_with = "millis")]
+    pub max_loss_window: Duration,
+}
+
+/// Writes a `Duration` as whole milliseconds, like the CSV columns.
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
+
+impl CadenceRun {
+    pub const CSV_COLUMNS: [&'static str; 7] = [
+        "duration_ms",
+        "ops_per_sec",
+        "backups",
+        "backup_ms",
+        "max_lost_transactions",
+        "max_lost_bytes",
+        "max_loss_window_ms",
+    ];
+
+    /// The values of `CSV_COLUMNS`.
+    pub fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.duration.as_millis().to_string(),
+            self.throughput.to_string(),
+            self.backups.to_string(),
+            self.backup_time.as_millis().to_string(),
+            self.max_lost_transactions.to_string(),
+            self.max_lost_bytes.to_string(),
+            self.max_loss_window.as_millis().to_string(),
+        ]
+    }
+}
+
+/// Executes the workload against the file store, backing it up to
+/// `BACKUP_PATH` whenever `policy` asks for it.
+fn measure(config: &WorkloadConfig, policy: BackupPolicy) -> CadenceRun {
+    let workload = Workload::generate(config);
+    let db = FileStoreDatabase::<Key, Value>::new();
+    let table = db.empty_table(TABLE_NAME);
+    table.execute(FileStore::build_transaction(workload.preload));
+    db.backup(BACKUP_PATH);
+
+    let transactions = workload
+        .transactions
+        .into_iter()
+        .map(|operations| (bytes_set(&operations), FileStore::build_transaction(operations)))
+        .collect::<Vec<_>>();
+
+    let mut backups = 0;
+    let mut backup_time = Duration::ZERO;
+    let mut max_lost_transactions = 0;
+    let mut max_lost_bytes = 0;
+    let mut max_loss_window = Duration::ZERO;
+
+    let mut dirty_transactions = 0;
+    let mut dirty_bytes = 0;
+    // Commit of the oldest transaction not backed up yet.
+    let mut oldest_dirty = None;
+
+    let start = Instant::now();
+    let mut last_backup = start;
+    for (bytes, transaction) in transactions {
+        table.execute(transaction);
+        dirty_transactions += 1;
+        dirty_bytes += bytes;
+        oldest_dirty.get_or_insert_with(Instant::now);
+        max_lost_transactions = max_lost_transactions.max(dirty_transactions);
+        max_lost_bytes = max_lost_bytes.max(dirty_bytes);
+
+        if policy.due(dirty_transactions, dirty_bytes, last_backup.elapsed()) {
+            let backup_start = Instant::now();
+            db.backup(BACKUP_PATH);
+            last_backup = Instant::now();
+            backups += 1;
+            backup_time += last_backup - backup_start;
+            max_loss_window = max_loss_window.max(last_backup - oldest_dirty.take().unwrap());
+            dirty_transactions = 0;
+            dirty_bytes = 0;
+        }
+    }
+    let duration = start.elapsed();
+    if let Some(oldest_dirty) = oldest_dirty {
+        max_loss_window = max_loss_window.max(oldest_dirty.elapsed());
+    }
+    std::fs::remove_dir_all(BACKUP_PATH).unwrap();
+
+    CadenceRun {
+        duration,
+        throughput: (config.transaction_size * config.transaction_count) as f64
+            / duration.as_secs_f64(),
+        backups,
+        backup_time,
+        max_lost_transactions,
+        max_lost_bytes,
+        max_loss_window,
+    }
+}
+
+/// Runs `config` `repetitions` times under every policy, on a single worker
+/// in a closed loop as the file store backs up from one thread, and writes
+/// the single runs to `{file_name}.csv` and the mean throughput against the
+/// worst loss window over all repetitions of a policy to
+/// `{file_name}_summary.csv`.
+/// Every run is recorded to `{file_name}.jsonl` and `history`, and its CSV
+/// row carries the run id and the `Host::id`.
+pub fn run_cadence(
+    config: &WorkloadConfig,
+    policies: &[BackupPolicy],
+    repetitions: usize,
+    output_dir: &Path,
+    file_name: &str,
+    history: &Path,
+) {
+    // The file store backing up, under other policies than every transaction.
+    let backend = Backend::FileBackup;
+    let revision = manifest::revision(backend);
+    let recorder = Recorder::create(output_dir, file_name, history);
+
+    let mut wtr = csv::Writer::from_path(output_dir.join(format!("{}.csv", file_name))).unwrap();
+    let mut header = vec!["backup_policy"];
+    header.extend(WorkloadConfig::CSV_COLUMNS);
+    header.extend(["repetition", "run_id", "host"]);
+    header.extend(CadenceRun::CSV_COLUMNS);
+    wtr.write_record(header).unwrap();
+
+    let mut summary_wtr =
+        csv::Writer::from_path(output_dir.join(format!("{}_summary.csv", file_name))).unwrap();
+    let mut header = vec!["backup_policy"];
+    header.extend(WorkloadConfig::CSV_COLUMNS);
+    header.extend([
+        "repetitions",
+        "mean_ops_per_sec",
+        "ci95_low_ops_per_sec",
+        "ci95_high_ops_per_sec",
+        "max_lost_transactions",
+        "max_lost_bytes",
+        "max_loss_window_ms",
+    ]);
+    summary_wtr.write_record(header).unwrap();
+
+    for (point, &policy) in policies.iter().enumerate() {
+        let runs = (0..repetitions)
+            .map(|repetition| {
+                let started_at = SystemTime::now();
+                let run = measure(config, policy);
+                let ended_at = SystemTime::now();
+                let id = recorder.run_id(point, repetition);
+                recorder.record(&RunManifest {
+                    run_id: &id,
+                    backend: backend.name(),
+                    revision: revision.as_deref(),
+                    scenario: file_name,
+                    repetition,
+                    seed: config.seed,
+                    workload: config,
+                    host_id: &recorder.host_id,
+                    host: &recorder.host,
+                    started_at_ms: unix_ms(started_at),
+                    ended_at_ms: unix_ms(ended_at),
+                    result: Measurement::Cadence { policy, run: &run },
+                    verified: None,
+                    divergence: None,
+                });
+                println!(
+                    "Backup policy {}: {:.0} ops/sec, {} backups, up to {} transactions or {:?} lost",
+                    policy, run.throughput, run.backups, run.max_lost_transactions, run.max_loss_window
+                );
+
+                let mut record = vec![policy.to_string()];
+                record.extend(config.csv_record());
+                record.extend([repetition.to_string(), id, recorder.host_id.clone()]);
+                record.extend(run.csv_record());
+                wtr.write_record(record).unwrap();
+                run
+            })
+            .collect::<Vec<_>>();
+
+        let throughputs = runs.iter().map(|run| run.throughput).collect::<Vec<_>>();
+        let summary = Summary::of(&throughputs);
+        let mut record = vec![policy.to_string()];
+        record.extend(config.csv_record());
+        record.extend([
+            repetitions.to_string(),
+            summary.mean.to_string(),
+            summary.ci95_low.to_string(),
+            summary.ci95_high.to_string(),
+            runs.iter().map(|run| run.max_lost_transactions).max().unwrap().to_string(),
+            runs.iter().map(|run| run.max_lost_bytes).max().unwrap().to_string(),
+            runs.iter()
+                .map(|run| run.max_loss_window)
+                .max()
+                .unwrap()
+                .as_millis()
+                .to_string(),
+        ]);
+        summary_wtr.write_record(record).unwrap();
+    }
+    wtr.flush().unwrap();
+    summary_wtr.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_bound_what_a_crash_loses() {
+        let config = WorkloadConfig::inserts(42, 10, 100, 8);
+        let bytes = bytes_set(&Workload::generate(&config).transactions[0]);
+
+        // A single test, the runs share the backup directory.
+        let never = measure(&config, BackupPolicy::Never);
+        assert_eq!((never.backups, never.max_lost_transactions), (0, 100));
+
+        let every_ten = measure(&config, BackupPolicy::Transactions(10));
+        assert_eq!((every_ten.backups, every_ten.max_lost_transactions), (10, 10));
+
+        let dirty = measure(&config, BackupPolicy::DirtyBytes(3 * bytes));
+        assert_eq!((dirty.backups, dirty.max_lost_transactions), (33, 3));
+        assert_eq!(dirty.max_lost_bytes, 3 * bytes);
+    }
+
+    #[test]
+    fn interval_policies_wait_for_the_interval() {
+        let policy: BackupPolicy = "interval:100".parse().unwrap();
+        assert!(!policy.due(50, 1 << 20, Duration::from_millis(99)));
+        assert!(policy.due(1, 0, Duration::from_millis(100)));
+        assert!("interval:0".parse::<BackupPolicy>().is_err());
+    }
+}
@@ -0,0 +1,1162 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+
+use crate::arrival::{Arrival, ArrivalProcess};
+use crate::backend::Backend;
+use crate::cadence::{run_cadence, BackupPolicy};
+use crate::crash;
+use crate::history::{Filter, History, StoredRun, DEFAULT_HISTORY_PATH};
+use crate::manifest::Recorder;
+use crate::recovery::run_recovery;
+use crate::helpers::{
+    create_offered_load_test, create_percentage_test, create_simple_test, create_thread_test,
+    create_transaction_big_size_test, create_transaction_size_test, create_value_size_test,
+    run_points, RunOptions, DEFAULT_CV_THRESHOLD, DEFAULT_REPETITIONS, DEFAULT_WARMUP,
+};
+use crate::scenario::Scenario;
+use crate::soak::run_soak;
+use crate::distribution::KeyDistribution;
+use crate::workload::{random_seed, WorkloadConfig, DEFAULT_KEY_SIZE, DEFAULT_VALUE_SIZE};
+use crate::ycsb::YcsbWorkload;
+
+/// Benchmarks the tenaciouszebra persistence branches against each other.
+#[derive(Parser)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct CommonArgs {
+    /// Backend to run against, can be repeated. Runs every backend when omitted.
+    #[arg(short, long = "backend", value_enum)]
+    backends: Vec<Backend>,
+
+    /// Directory the result and CPU stats CSVs are written to.
+    #[arg(short, long, default_value = "results")]
+    output_dir: PathBuf,
+
+    /// Runs per configuration that are thrown away before measuring.
+    #[arg(long, default_value_t = DEFAULT_WARMUP)]
+    warmup: usize,
+
+    /// Measured runs per configuration.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_REPETITIONS,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    repetitions: usize,
+
+    /// Flags configurations whose coefficient of variation is above this.
+    #[arg(long, default_value_t = DEFAULT_CV_THRESHOLD)]
+    cv_threshold: f64,
+
+    /// Checks what every measured run read and the table it left against an
+    /// in-memory model. The responses are kept during the run and their values
+    /// copied after it is timed. Runs that diverge are marked as failed.
+    #[arg(long)]
+    verify: bool,
+
+    /// SQLite database every measured run is appended to.
+    #[arg(long, default_value = DEFAULT_HISTORY_PATH)]
+    history: PathBuf,
+
+    #[command(flatten)]
+    workload: WorkloadArgs,
+
+    #[command(flatten)]
+    execution: ExecutionArgs,
+}
+
+#[derive(Args)]
+struct WorkloadArgs {
+    /// Seed of the generated workload. A random one is picked and printed when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How read keys are picked: sequential, uniform, zipfian[:theta],
+    /// hotspot[:hot_fraction[:hot_operation_fraction]] or latest[:theta].
+    #[arg(long, default_value = "sequential")]
+    read_distribution: KeyDistribution,
+
+    /// How written keys are picked, same values as --read-distribution.
+    /// Sequential inserts new keys, everything else overwrites existing ones.
+    #[arg(long, default_value = "sequential")]
+    write_distribution: KeyDistribution,
+
+    /// Key length, in bytes or with a KiB/MiB suffix.
+    #[arg(long, default_value_t = DEFAULT_KEY_SIZE, value_parser = parse_size)]
+    key_size: usize,
+
+    /// Value length, in bytes or with a KiB/MiB suffix.
+    #[arg(long, default_value_t = DEFAULT_VALUE_SIZE, value_parser = parse_size)]
+    value_size: usize,
+
+    /// Percentage of operations deleting an existing key.
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..=100))]
+    delete_percentage: i32,
+
+    /// Percentage of operations reading a key and setting it again. The set
+    /// takes the first slots of the next transaction, a transaction cannot
+    /// touch a key twice, so the pair is not atomic. Those drawn in the last
+    /// transaction stay plain reads, the CSVs count the completed pairs as
+    /// read_modify_writes.
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..=100))]
+    read_modify_write_percentage: i32,
+}
+
+/// How the transactions of the sweeps are executed. The soak and cadence
+/// tests run a single worker in a closed loop.
+#[derive(Args)]
+struct ExecutionArgs {
+    /// Worker threads executing the transactions against the same table. The
+    /// file backup runs single threaded, sweeps skip it for more.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+
+    /// When transactions are issued: closed, constant:<ops_per_sec> or
+    /// poisson:<ops_per_sec>. The file backup runs closed loops, sweeps skip
+    /// it for the others. Open-loop sets its own arrivals and refuses this.
+    #[arg(long, default_value = "closed")]
+    arrival: Arrival,
+}
+
+impl WorkloadArgs {
+    /// A single threaded closed loop over the workload.
+    fn config(
+        &self,
+        write_percentage: i32,
+        transaction_size: usize,
+        transaction_count: usize,
+    ) -> WorkloadConfig {
+        WorkloadConfig {
+            write_percentage,
+            delete_percentage: self.delete_percentage,
+            read_modify_write_percentage: self.read_modify_write_percentage,
+            transaction_size,
+            transaction_count,
+            threads: 1,
+            arrival: Arrival::Closed,
+            seed: resolve_seed(self.seed),
+            read_distribution: self.read_distribution,
+            write_distribution: self.write_distribution,
+            record_count: None,
+            key_size: self.key_size,
+            value_size: self.value_size,
+        }
+    }
+}
+
+impl CommonArgs {
+    /// The backends to run, without the file backup when the workload is not
+    /// a single threaded closed loop.
+    fn backends(&self) -> Vec<Backend> {
+        let backends = if self.backends.is_empty() {
+            Backend::value_variants().to_vec()
+        } else {
+            self.backends.clone()
+        };
+        if self.execution.threads == 1 && self.execution.arrival == Arrival::Closed {
+            return backends;
+        }
+        backends
+            .into_iter()
+            .filter(|&backend| {
+                let skip = backend == Backend::FileBackup;
+                if skip {
+                    println!("Skipping {}, it runs single threaded closed loops", backend.name());
+                }
+                !skip
+            })
+            .collect()
+    }
+
+    /// The workload, executed as `--threads` and `--arrival` say.
+    fn config(
+        &self,
+        write_percentage: i32,
+        transaction_size: usize,
+        transaction_count: usize,
+    ) -> WorkloadConfig {
+        WorkloadConfig {
+            threads: self.execution.threads.into(),
+            arrival: self.execution.arrival,
+            ..self
+                .workload
+                .config(write_percentage, transaction_size, transaction_count)
+        }
+    }
+
+    fn run_options(&self) -> RunOptions<'_> {
+        std::fs::create_dir_all(&self.output_dir).unwrap();
+        RunOptions {
+            output_dir: &self.output_dir,
+            warmup: self.warmup,
+            repetitions: self.repetitions,
+            cv_threshold: self.cv_threshold,
+            verify: self.verify,
+            history: &self.history,
+        }
+    }
+}
+
+#[derive(Args)]
+struct SimpleArgs {
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    #[arg(long, default_value_t = 100000)]
+    transaction_size: usize,
+
+    #[arg(long, default_value_t = 100)]
+    transaction_count: usize,
+}
+
+#[derive(Args)]
+struct WritePercentageArgs {
+    /// Step between two write percentages, starting at 0.
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    step: usize,
+
+    #[arg(long, default_value_t = 100000)]
+    transaction_size: usize,
+
+    #[arg(long, default_value_t = 100)]
+    transaction_count: usize,
+}
+
+#[derive(Args)]
+struct TransactionSizeArgs {
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    /// Smallest transaction size, as a power of ten.
+    #[arg(long, default_value_t = 2)]
+    min_power: u32,
+
+    /// Total number of operations per run, as a power of ten.
+    #[arg(long, default_value_t = 7)]
+    total_power: u32,
+}
+
+#[derive(Args)]
+struct BigSizeArgs {
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    /// Smallest transaction size, as a power of ten.
+    #[arg(long, default_value_t = 4)]
+    min_power: u32,
+
+    /// Largest transaction size (exclusive), as a power of ten.
+    #[arg(long, default_value_t = 8)]
+    max_power: u32,
+
+    #[arg(long, default_value_t = 100)]
+    transaction_count: usize,
+}
+
+#[derive(Args)]
+struct ValueSizeArgs {
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    /// Value lengths to sweep, in bytes or with a KiB/MiB suffix.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_size,
+        default_value = "8,64,512,4KiB,16KiB,64KiB"
+    )]
+    value_sizes: Vec<usize>,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_size: usize,
+
+    #[arg(long, default_value_t = 100)]
+    transaction_count: usize,
+}
+
+#[derive(Args)]
+struct ThreadsArgs {
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    /// Numbers of worker threads to sweep.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "1,2,4,8,16",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    thread_counts: Vec<usize>,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_size: usize,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_count: usize,
+}
+
+#[derive(Args)]
+struct OpenLoopArgs {
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    /// How transactions arrive at each offered load.
+    #[arg(long, value_enum, default_value = "poisson")]
+    process: ArrivalProcess,
+
+    /// Offered loads to sweep, in operations per second.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "1000,10000,100000,1000000",
+        value_parser = parse_rate
+    )]
+    rates: Vec<f64>,
+
+    #[arg(long, default_value_t = 100)]
+    transaction_size: usize,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_count: usize,
+}
+
+#[derive(Args)]
+struct YcsbArgs {
+    /// YCSB workload to run, can be repeated. Runs every supported workload when omitted.
+    #[arg(short, long = "workload", value_enum)]
+    workloads: Vec<YcsbWorkload>,
+
+    /// Keys loaded before measuring, at least the transaction size, as a
+    /// transaction touches every key once.
+    #[arg(long, default_value_t = 100000)]
+    record_count: usize,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_size: usize,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_count: usize,
+}
+
+#[derive(Args)]
+struct CrashArgs {
+    /// Backend to test, can be repeated. Tests every backend that keeps its
+    /// table on disk when omitted.
+    #[arg(short, long = "backend", value_parser = durable_backend())]
+    backends: Vec<Backend>,
+
+    #[arg(short, long, default_value = "results")]
+    output_dir: PathBuf,
+
+    /// Kills per backend.
+    #[arg(long, default_value_t = 10)]
+    trials: usize,
+
+    /// SQLite database every trial is appended to.
+    #[arg(long, default_value = DEFAULT_HISTORY_PATH)]
+    history: PathBuf,
+
+    #[command(flatten)]
+    workload: CrashWorkloadArgs,
+}
+
+/// The crash workload, passed on unchanged to the child process.
+#[derive(Args)]
+struct CrashWorkloadArgs {
+    /// Seed of the workload and the kill points. A random one is picked and
+    /// printed when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[arg(long, default_value_t = 100)]
+    transaction_size: usize,
+
+    #[arg(
+        long,
+        default_value_t = 100,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    transaction_count: usize,
+
+    /// Value length, in bytes or with a KiB/MiB suffix.
+    #[arg(long, default_value_t = DEFAULT_VALUE_SIZE, value_parser = parse_size)]
+    value_size: usize,
+}
+
+impl CrashWorkloadArgs {
+    fn config(&self, seed: u64) -> WorkloadConfig {
+        WorkloadConfig::inserts(seed, self.transaction_size, self.transaction_count, self.value_size)
+    }
+}
+
+#[derive(Args)]
+struct RecoveryArgs {
+    /// Backend to restart, can be repeated. Restarts every backend that keeps
+    /// its table on disk when omitted.
+    #[arg(short, long = "backend", value_parser = durable_backend())]
+    backends: Vec<Backend>,
+
+    #[arg(short, long, default_value = "results")]
+    output_dir: PathBuf,
+
+    /// Restarts per store size.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_REPETITIONS,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    repetitions: usize,
+
+    /// SQLite database every restart is appended to.
+    #[arg(long, default_value = DEFAULT_HISTORY_PATH)]
+    history: PathBuf,
+
+    /// Seed of the inserted values. A random one is picked and printed when omitted.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Keys inserted before the restart, one store per value.
+    #[arg(long, value_delimiter = ',', default_value = "10000,100000,1000000")]
+    record_counts: Vec<usize>,
+
+    /// Keys inserted per transaction, so a store holds
+    /// `record_count / transaction_size` transactions in its log.
+    #[arg(long, default_value_t = 1000)]
+    transaction_size: usize,
+
+    /// Value length, in bytes or with a KiB/MiB suffix.
+    #[arg(long, default_value_t = DEFAULT_VALUE_SIZE, value_parser = parse_size)]
+    value_size: usize,
+}
+
+#[derive(Args)]
+struct SoakArgs {
+    /// Backend to soak.
+    #[arg(short, long, value_enum)]
+    backend: Backend,
+
+    #[arg(short, long, default_value = "results")]
+    output_dir: PathBuf,
+
+    /// How long to run, like 30s, 10m or 4h.
+    #[arg(long, default_value = "1h", value_parser = parse_duration)]
+    duration: Duration,
+
+    /// Length of a measurement window.
+    #[arg(long, default_value = "1m", value_parser = parse_duration)]
+    interval: Duration,
+
+    /// SQLite database every window is appended to.
+    #[arg(long, default_value = DEFAULT_HISTORY_PATH)]
+    history: PathBuf,
+
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_size: usize,
+
+    /// Transactions drawn at a time, the next batch is drawn from the next seed.
+    #[arg(long, default_value_t = 10000)]
+    transaction_count: usize,
+
+    #[command(flatten)]
+    workload: WorkloadArgs,
+}
+
+#[derive(Args)]
+struct CadenceArgs {
+    #[arg(short, long, default_value = "results")]
+    output_dir: PathBuf,
+
+    /// Runs per backup policy.
+    #[arg(
+        long,
+        default_value_t = DEFAULT_REPETITIONS,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    repetitions: usize,
+
+    /// SQLite database every run is appended to.
+    #[arg(long, default_value = DEFAULT_HISTORY_PATH)]
+    history: PathBuf,
+
+    /// Backup policies to compare: never, transactions:<count>,
+    /// interval:<ms> or dirty-bytes:<bytes>.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "never,transactions:1,transactions:10,transactions:100,interval:10,interval:100,dirty-bytes:65536,dirty-bytes:1048576"
+    )]
+    policies: Vec<BackupPolicy>,
+
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(i32).range(0..=100))]
+    write_percentage: i32,
+
+    #[arg(long, default_value_t = 100)]
+    transaction_size: usize,
+
+    #[arg(long, default_value_t = 1000)]
+    transaction_count: usize,
+
+    #[command(flatten)]
+    workload: WorkloadArgs,
+}
+
+#[derive(Args)]
+struct HistoryArgs {
+    /// SQLite database to read.
+    #[arg(long, default_value = DEFAULT_HISTORY_PATH)]
+    db: PathBuf,
+
+    #[command(subcommand)]
+    action: HistoryAction,
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Prints the matching runs, oldest first.
+    List(HistoryFilterArgs),
+    /// Writes the matching runs to a file.
+    Export {
+        #[command(flatten)]
+        filter: HistoryFilterArgs,
+
+        /// CSV of the stored columns or the full JSON manifest per line.
+        #[arg(long, value_enum, default_value = "csv")]
+        format: ExportFormat,
+
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct HistoryFilterArgs {
+    #[arg(short, long, value_enum)]
+    backend: Option<Backend>,
+
+    /// Part of the scenario or sweep name, like `write_percentage`.
+    #[arg(long)]
+    scenario: Option<String>,
+
+    /// Start of the tenaciouszebra commit.
+    #[arg(long)]
+    revision: Option<String>,
+
+    /// Host fingerprint id, as in the `host` column of the result CSVs.
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Only runs started at or after this many milliseconds since the epoch.
+    #[arg(long)]
+    since_ms: Option<i64>,
+
+    /// Only the newest runs.
+    #[arg(long)]
+    limit: Option<usize>,
+}
+
+impl HistoryFilterArgs {
+    fn filter(&self) -> Filter {
+        Filter {
+            backend: self.backend.map(|backend| backend.name().to_string()),
+            scenario: self.scenario.clone(),
+            revision: self.revision.clone(),
+            host_id: self.host.clone(),
+            since_ms: self.since_ms,
+            limit: self.limit,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs a single configuration.
+    Simple {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: SimpleArgs,
+    },
+    /// Sweeps the share of writes from 0 to 100 percent.
+    WritePercentage {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: WritePercentageArgs,
+    },
+    /// Sweeps the transaction size at a constant number of operations.
+    TransactionSize {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: TransactionSizeArgs,
+    },
+    /// Sweeps the transaction size at a constant number of transactions.
+    BigSize {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: BigSizeArgs,
+    },
+    /// Sweeps the value length at a constant number of operations.
+    ValueSize {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: ValueSizeArgs,
+    },
+    /// Sweeps the number of worker threads sharing the table.
+    Threads {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: ThreadsArgs,
+    },
+    /// Issues transactions at increasing offered loads and records latency
+    /// from their intended start, finding where each backend saturates.
+    OpenLoop {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: OpenLoopArgs,
+    },
+    /// Runs every sweep above with its default parameters.
+    All {
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Runs YCSB core workloads.
+    Ycsb {
+        #[command(flatten)]
+        common: CommonArgs,
+        #[command(flatten)]
+        args: YcsbArgs,
+    },
+    /// Runs the scenario described in a TOML file.
+    Scenario {
+        path: PathBuf,
+
+        /// Overrides the output directory set in the scenario.
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+
+        /// SQLite database every measured run is appended to.
+        #[arg(long, default_value = DEFAULT_HISTORY_PATH)]
+        history: PathBuf,
+    },
+    /// Fills every backend that persists, drops it and times the restart
+    /// until the first read.
+    Recovery(RecoveryArgs),
+    /// Backs the file store up under different policies and compares the
+    /// throughput with the worst-case data-loss window.
+    Cadence(CadenceArgs),
+    /// Runs a steady workload against one backend for hours and flags
+    /// throughput degradation and growing memory, latency or disk usage.
+    Soak(SoakArgs),
+    /// Kills a process running the workload and checks what each backend
+    /// recovers from disk.
+    Crash(CrashArgs),
+    /// Lists and exports the runs kept in the history database.
+    History(HistoryArgs),
+    /// The process killed by `crash`.
+    #[command(hide = true)]
+    CrashChild {
+        #[arg(long, value_parser = durable_backend())]
+        backend: Backend,
+        #[command(flatten)]
+        workload: CrashWorkloadArgs,
+    },
+}
+
+pub fn run(cli: Cli) {
+    match cli.command {
+        Command::Simple { common, args } => run_simple(&common, &args),
+        Command::WritePercentage { common, args } => run_write_percentage(&common, &args),
+        Command::TransactionSize { common, args } => run_transaction_size(&common, &args),
+        Command::BigSize { common, args } => run_big_size(&common, &args),
+        Command::ValueSize { common, args } => run_value_size(&common, &args),
+        Command::Threads { common, args } => run_threads(&common, &args),
+        Command::OpenLoop { common, args } => {
+            // The sweep sets the arrivals itself, from `--process` and `--rates`.
+            if common.execution.arrival != Arrival::Closed {
+                usage_error("--arrival does not apply to open-loop, use --process and --rates");
+            }
+            run_open_loop(&common, &args)
+        }
+        Command::All { common } => {
+            run_simple(&common, &default_args());
+            run_write_percentage(&common, &default_args());
+            run_transaction_size(&common, &default_args());
+            run_big_size(&common, &default_args());
+            run_value_size(&common, &default_args());
+            run_threads(&common, &default_args());
+            run_open_loop(&common, &default_args());
+        }
+        Command::Ycsb { common, args } => run_ycsb(&common, &args),
+        Command::Scenario {
+            path,
+            output_dir,
+            history,
+        } => run_scenario(&path, output_dir, &history),
+        Command::Recovery(args) => run_recovery_test(&args),
+        Command::Cadence(args) => run_cadence_test(&args),
+        Command::Soak(args) => run_soak_test(&args),
+        Command::Crash(args) => run_crash(&args),
+        Command::History(args) => run_history(&args),
+        Command::CrashChild { backend, workload } => {
+            let seed = workload.seed.expect("The crash child needs a seed");
+            crash::run_child(backend, &workload.config(seed));
+        }
+    }
+}
+
+/// Parses a `--backend` that keeps its table on disk, the others have
+/// nothing to reopen.
+fn durable_backend() -> impl TypedValueParser<Value = Backend> {
+    PossibleValuesParser::new(
+        durable_backends(&[])
+            .into_iter()
+            .map(|backend| backend.to_possible_value().unwrap()),
+    )
+    .map(|name| <Backend as ValueEnum>::from_str(&name, false).unwrap())
+}
+
+/// The `--backend`s given to `durable_backend`, or every backend keeping its
+/// table on disk when there are none.
+fn durable_backends(backends: &[Backend]) -> Vec<Backend> {
+    if backends.is_empty() {
+        Backend::value_variants()
+            .iter()
+            .copied()
+            .filter(|backend| backend.is_durable())
+            .collect()
+    } else {
+        backends.to_vec()
+    }
+}
+
+/// Parses a byte count like `512`, `4KiB` or `1MiB`.
+fn parse_size(value: &str) -> Result<usize, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, ""),
+    };
+    let multiplier = match unit.trim() {
+        "" | "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        _ => return Err(format!("Unknown size unit in {}, use B, KiB or MiB", value)),
+    };
+    number
+        .parse::<usize>()
+        .map(|number| number * multiplier)
+        .map_err(|_| format!("Invalid size {}", value))
+}
+
+/// Parses a duration like `500ms`, `30s`, `10m` or `4h`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, ""),
+    };
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid duration {}", value))?;
+    match unit.trim() {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 60 * 60)),
+        _ => Err(format!("Unknown duration unit in {}, use ms, s, m or h", value)),
+    }
+}
+
+/// Parses an offered load in operations per second.
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate > 0.0 => Ok(rate),
+        _ => Err(format!("Invalid offered load {}, it has to be positive", value)),
+    }
+}
+
+fn resolve_seed(seed: Option<u64>) -> u64 {
+    let seed = seed.unwrap_or_else(random_seed);
+    println!("Using seed {}", seed);
+    seed
+}
+
+/// Parses an empty argument list, so `all` uses the same defaults as the
+/// individual subcommands.
+fn default_args<T: Args>() -> T {
+    #[derive(Parser)]
+    struct Defaults<T: Args> {
+        #[command(flatten)]
+        args: T,
+    }
+
+    Defaults::<T>::parse_from(["heart"]).args
+}
+
+fn run_simple(common: &CommonArgs, args: &SimpleArgs) {
+    let options = common.run_options();
+    let base =
+        common.config(args.write_percentage, args.transaction_size, args.transaction_count);
+    for backend in common.backends() {
+        create_simple_test(
+            backend,
+            &options,
+            &format!("simple_{}", backend.name()),
+            &base,
+        );
+    }
+}
+
+fn run_write_percentage(common: &CommonArgs, args: &WritePercentageArgs) {
+    let options = common.run_options();
+    let base = common.config(0, args.transaction_size, args.transaction_count);
+    for backend in common.backends() {
+        create_percentage_test(
+            backend,
+            &options,
+            &format!("write_percentage_{}", backend.name()),
+            &base,
+            args.step,
+        );
+    }
+}
+
+/// Exits with a usage error unless `--min-power` is below the power of ten
+/// given as `max_flag`, the sweep would be empty otherwise.
+fn check_powers(min_power: u32, max_power: u32, max_flag: &str) {
+    if min_power >= max_power {
+        usage_error(&format!(
+            "--min-power {} has to be below {} {}",
+            min_power, max_flag, max_power
+        ));
+    }
+}
+
+/// Exits like clap does for arguments that cannot be combined.
+fn usage_error(message: &str) -> ! {
+    Cli::command().error(ErrorKind::ArgumentConflict, message).exit()
+}
+
+fn run_transaction_size(common: &CommonArgs, args: &TransactionSizeArgs) {
+    check_powers(args.min_power, args.total_power, "--total-power");
+    let options = common.run_options();
+    let base = common.config(args.write_percentage, 0, 0);
+    for backend in common.backends() {
+        create_transaction_size_test(
+            backend,
+            &options,
+            &format!("transaction_size_{}", backend.name()),
+            &base,
+            args.min_power,
+            args.total_power,
+        );
+    }
+}
+
+fn run_big_size(common: &CommonArgs, args: &BigSizeArgs) {
+    check_powers(args.min_power, args.max_power, "--max-power");
+    let options = common.run_options();
+    let base = common.config(args.write_percentage, 0, args.transaction_count);
+    for backend in common.backends() {
+        create_transaction_big_size_test(
+            backend,
+            &options,
+            &format!("transaction_big_size_{}", backend.name()),
+            &base,
+            args.min_power,
+            args.max_power,
+        );
+    }
+}
+
+fn run_value_size(common: &CommonArgs, args: &ValueSizeArgs) {
+    let options = common.run_options();
+    let base =
+        common.config(args.write_percentage, args.transaction_size, args.transaction_count);
+    for backend in common.backends() {
+        create_value_size_test(
+            backend,
+            &options,
+            &format!("value_size_{}", backend.name()),
+            &base,
+            &args.value_sizes,
+        );
+    }
+}
+
+fn run_threads(common: &CommonArgs, args: &ThreadsArgs) {
+    let options = common.run_options();
+    let base =
+        common.config(args.write_percentage, args.transaction_size, args.transaction_count);
+    for backend in common.backends() {
+        if backend == Backend::FileBackup {
+            println!("Skipping {}, it runs single threaded", backend.name());
+            continue;
+        }
+        create_thread_test(
+            backend,
+            &options,
+            &format!("threads_{}", backend.name()),
+            &base,
+            &args.thread_counts,
+        );
+    }
+}
+
+fn run_open_loop(common: &CommonArgs, args: &OpenLoopArgs) {
+    let options = common.run_options();
+    let base =
+        common.config(args.write_percentage, args.transaction_size, args.transaction_count);
+    for backend in common.backends() {
+        if backend == Backend::FileBackup {
+            println!("Skipping {}, it runs closed loop", backend.name());
+            continue;
+        }
+        create_offered_load_test(
+            backend,
+            &options,
+            &format!("open_loop_{}", backend.name()),
+            &base,
+            args.process,
+            &args.rates,
+        );
+    }
+}
+
+fn run_ycsb(common: &CommonArgs, args: &YcsbArgs) {
+    let options = common.run_options();
+    let base = WorkloadConfig {
+        record_count: Some(args.record_count),
+        ..common.config(0, args.transaction_size, args.transaction_count)
+    };
+    let workloads = if args.workloads.is_empty() {
+        YcsbWorkload::value_variants().to_vec()
+    } else {
+        args.workloads.clone()
+    };
+
+    for workload in workloads {
+        let Some(config) = workload.apply(&base) else {
+            println!("Skipping YCSB workload {}, range scans are not supported", workload.name());
+            continue;
+        };
+        for backend in common.backends() {
+            create_simple_test(
+                backend,
+                &options,
+                &format!("ycsb_{}_{}", workload.name(), backend.name()),
+                &config,
+            );
+        }
+    }
+}
+
+fn run_scenario(path: &Path, output_dir: Option<PathBuf>, history: &Path) {
+    let scenario = Scenario::from_file(path);
+    let output_dir = output_dir
+        .or_else(|| scenario.output_dir.clone())
+        .unwrap_or_else(|| PathBuf::from("results"));
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let options = RunOptions {
+        output_dir: &output_dir,
+        warmup: scenario.warmup,
+        repetitions: scenario.repetitions,
+        cv_threshold: scenario.cv_threshold,
+        verify: scenario.verify,
+        history,
+    };
+    let seed = resolve_seed(scenario.seed);
+
+    println!("Running scenario {} from {}", scenario.name, path.display());
+    for &backend in &scenario.backends {
+        run_points(
+            backend,
+            scenario.run_matrix(seed),
+            &options,
+            &format!("{}_{}", scenario.name, backend.name()),
+        );
+    }
+}
+
+fn run_crash(args: &CrashArgs) {
+    std::fs::create_dir_all(&args.output_dir).unwrap();
+    let config = args.workload.config(resolve_seed(args.workload.seed));
+    let recorder = Recorder::create(&args.output_dir, "crash", &args.history);
+    let results = durable_backends(&args.backends)
+        .into_iter()
+        .enumerate()
+        .map(|(point, backend)| {
+            let trials = crash::run_trials(backend, &config, args.trials, &recorder, point);
+            (backend, trials)
+        })
+        .collect::<Vec<_>>();
+    crash::write_results(&args.output_dir, &config, &results, &recorder);
+}
+
+fn run_cadence_test(args: &CadenceArgs) {
+    std::fs::create_dir_all(&args.output_dir).unwrap();
+    let config = args
+        .workload
+        .config(args.write_percentage, args.transaction_size, args.transaction_count);
+    run_cadence(
+        &config,
+        &args.policies,
+        args.repetitions,
+        &args.output_dir,
+        "backup_cadence",
+        &args.history,
+    );
+}
+
+fn run_soak_test(args: &SoakArgs) {
+    std::fs::create_dir_all(&args.output_dir).unwrap();
+    let config = args
+        .workload
+        .config(args.write_percentage, args.transaction_size, args.transaction_count);
+    run_soak(
+        args.backend,
+        &config,
+        args.duration,
+        args.interval,
+        &args.output_dir,
+        &format!("soak_{}", args.backend.name()),
+        &args.history,
+    );
+}
+
+fn run_history(args: &HistoryArgs) {
+    if !args.db.exists() {
+        panic!("No history at {}", args.db.display());
+    }
+    let history = History::open(&args.db);
+    match &args.action {
+        HistoryAction::List(filter) => {
+            let runs = history.runs(&filter.filter());
+            println!(
+                "{:<40} {:<20} {:<10} {:<16} {:>15} {:>14} {:>10} {:>8}",
+                "run id", "backend", "revision", "host", "started at ms", "ops/sec", "p99 us", "verified"
+            );
+            for run in &runs {
+                println!(
+                    "{:<40} {:<20} {:<10} {:<16} {:>15} {:>14} {:>10} {:>8}",
+                    run.run_id,
+                    run.backend,
+                    run.revision.as_deref().map(|x| &x[..x.len().min(10)]).unwrap_or("-"),
+                    run.host_id,
+                    run.started_at_ms,
+                    run.ops_per_sec.map(|x| format!("{:.0}", x)).unwrap_or_else(|| "-".to_string()),
+                    run.p99_us.map(|x| x.to_string()).unwrap_or_else(|| "-".to_string()),
+                    run.verified.map(|x| x.to_string()).unwrap_or_else(|| "-".to_string())
+                );
+            }
+            println!("{} runs", runs.len());
+        }
+        HistoryAction::Export {
+            filter,
+            format,
+            output,
+        } => {
+            let runs = history.runs(&filter.filter());
+            match format {
+                ExportFormat::Csv => {
+                    let mut wtr = csv::Writer::from_path(output).unwrap();
+                    wtr.write_record(StoredRun::CSV_COLUMNS).unwrap();
+                    for run in &runs {
+                        wtr.write_record(run.csv_record()).unwrap();
+                    }
+                    wtr.flush().unwrap();
+                }
+                ExportFormat::Jsonl => {
+                    let lines = runs.iter().map(|run| format!("{}\n", run.manifest));
+                    std::fs::write(output, lines.collect::<String>()).unwrap();
+                }
+            }
+            println!("Exported {} runs to {}", runs.len(), output.display());
+        }
+    }
+}
+
+fn run_recovery_test(args: &RecoveryArgs) {
+    std::fs::create_dir_all(&args.output_dir).unwrap();
+    let seed = resolve_seed(args.seed);
+    let points = args
+        .record_counts
+        .iter()
+        .map(|&record_count| {
+            WorkloadConfig::inserts(
+                seed,
+                args.transaction_size,
+                (record_count / args.transaction_size).max(1),
+                args.value_size,
+            )
+        })
+        .collect::<Vec<_>>();
+    for backend in durable_backends(&args.backends) {
+        run_recovery(
+            backend,
+            &points,
+            args.repetitions,
+            &args.output_dir,
+            &format!("recovery_{}", backend.name()),
+            &args.history,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_take_binary_units() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("4KiB"), Ok(4096));
+        assert_eq!(parse_size("1 MiB"), Ok(1 << 20));
+        // Decimal units would be off by 2.4 percent, so they are refused.
+        assert!(parse_size("4KB").is_err());
+        assert!(parse_size("KiB").is_err());
+    }
+
+    #[test]
+    fn durations_take_units_up_to_hours() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("4h"), Ok(Duration::from_secs(4 * 60 * 60)));
+        assert!(parse_duration("1.5s").is_err());
+        assert!(parse_duration("1d").is_err());
+    }
+
+    #[test]
+    fn offered_loads_have_to_be_positive() {
+        assert_eq!(parse_rate("2500.5"), Ok(2500.5));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("-10").is_err());
+        assert!(parse_rate("NaN").is_err());
+    }
+}
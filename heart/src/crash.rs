@@ -0,0 +1,398 @@
+use std::io::{BufRead, BufReader};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::backend::{with_durable_backend, Backend, Durable};
+use crate::manifest::{self, unix_ms, Measurement, Recorder, RunManifest};
+use crate::workload::{Operation, Value, Workload, WorkloadConfig};
+
+/// The signal `Child::kill` sends on Unix.
+const SIGKILL: i32 = 9;
+
+/// Runs the crash workload in this process, used by the child side of a trial.
+type ChildFunction = fn(&WorkloadConfig);
+
+/// Reopens what a killed child left behind and checks it.
+type VerifyFunction = fn(&Workload, usize) -> Verdict;
+
+/// What a reopened table holds of one transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recovered {
+    Complete,
+    Missing,
+    /// Some but not all of the transaction's writes are visible.
+    Partial,
+}
+
+/// Outcome of one kill and reopen.
+#[derive(Serialize)]
+pub struct Trial {
+    /// Acknowledged transactions after which the child was killed.
+    pub kill_after: usize,
+    /// Transactions whose `execute` returned before the child died.
+    pub acknowledged: usize,
+    /// State of the transaction that was executing when the child died,
+    /// `None` when the child finished every transaction.
+    pub in_flight: Option<Recovered>,
+    /// Why the trial failed, `None` when it passed.
+    pub failure: Option<String>,
+}
+
+struct Verdict {
+    in_flight: Option<Recovered>,
+    failure: Option<String>,
+}
+
+/// The crash test functions of `backend`.
+fn functions(backend: Backend) -> (ChildFunction, VerifyFunction) {
+    with_durable_backend!(backend, B => (child::<B> as ChildFunction, verify::<B> as VerifyFunction))
+}
+
+/// Entry point of the child process of a trial.
+pub fn run_child(backend: Backend, config: &WorkloadConfig) {
+    let (child, _) = functions(backend);
+    child(config);
+}
+
+/// Executes the workload, printing the number of acknowledged transactions
+/// after the preload and after every `execute` returns.
+fn child<B: Durable>(config: &WorkloadConfig) {
+    let workload = Workload::generate(config);
+    let backend = B::open();
+    backend.execute(B::build_transaction(workload.preload));
+    println!("0");
+    for (i, operations) in workload.transactions.into_iter().enumerate() {
+        backend.execute(B::build_transaction(operations));
+        println!("{}", i + 1);
+    }
+}
+
+fn verify<B: Durable>(workload: &Workload, acknowledged: usize) -> Verdict {
+    let backend = B::reopen();
+    let preload = recovered(&backend, &workload.preload);
+    let transactions = workload
+        .transactions
+        .iter()
+        .map(|operations| recovered(&backend, operations))
+        .collect::<Vec<_>>();
+    judge(preload, &transactions, acknowledged)
+}
+
+/// Passes when the preload and the `acknowledged` first transactions are
+/// complete, the one in flight is complete or missing and the later ones are
+/// missing.
+fn judge(preload: Recovered, transactions: &[Recovered], acknowledged: usize) -> Verdict {
+    if preload != Recovered::Complete {
+        return Verdict {
+            in_flight: None,
+            failure: Some("preload lost".to_string()),
+        };
+    }
+
+    let mut in_flight = None;
+    for (i, &state) in transactions.iter().enumerate() {
+        let expected = match i.cmp(&acknowledged) {
+            std::cmp::Ordering::Less => state == Recovered::Complete,
+            std::cmp::Ordering::Equal => {
+                in_flight = Some(state);
+                state != Recovered::Partial
+            }
+            std::cmp::Ordering::Greater => state == Recovered::Missing,
+        };
+        if !expected {
+            return Verdict {
+                in_flight,
+                failure: Some(format!("transaction {} is {:?}", i, state)),
+            };
+        }
+    }
+    Verdict {
+        in_flight,
+        failure: None,
+    }
+}
+
+fn recovered<B: Durable>(backend: &B, operations: &[Operation]) -> Recovered {
+    let (keys, values): (Vec<_>, Vec<_>) = operations
+        .iter()
+        .map(|operation| match operation {
+            Operation::Set(key, value) => (key.clone(), value),
+            _ => unreachable!("Crash workloads only insert"),
+        })
+        .unzip();
+    classify(&backend.read(&keys), &values)
+}
+
+/// What a read of the keys of a transaction returned, against the values the
+/// transaction set.
+fn classify(found: &[Option<Value>], expected: &[&Value]) -> Recovered {
+    let matching = found
+        .iter()
+        .zip(expected)
+        .filter(|(found, expected)| found.as_ref() == Some(**expected))
+        .count();
+    if matching == expected.len() {
+        Recovered::Complete
+    } else if found.iter().all(Option::is_none) {
+        Recovered::Missing
+    } else {
+        Recovered::Partial
+    }
+}
+
+/// Runs `trials` times: starts a child executing the workload in a fresh
+/// directory under the working directory, the one `Recorder` fingerprints,
+/// `SIGKILL`s it at a random point, reopens the directory and checks that
+/// every acknowledged transaction is there and the one in flight is either
+/// fully there or not at all. Every trial is recorded as a run of `point`.
+pub fn run_trials(
+    backend: Backend,
+    config: &WorkloadConfig,
+    trials: usize,
+    recorder: &Recorder,
+    point: usize,
+) -> Vec<Trial> {
+    let (_, verify) = functions(backend);
+    let revision = manifest::revision(backend);
+    let workload = Workload::generate(config);
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+    let root = std::env::current_dir()
+        .unwrap()
+        .join(format!("heart-crash-{}", std::process::id()));
+
+    let results = (0..trials)
+        .map(|index| {
+            let dir = root.join(format!("{}-{}", backend.name(), index));
+            std::fs::create_dir_all(&dir).unwrap();
+            let started_at = SystemTime::now();
+
+            let kill_after = rng.gen_range(0..config.transaction_count);
+            let (acknowledged, child_failure) =
+                kill_child(backend, config, &dir, kill_after, &mut rng);
+
+            let verdict = match child_failure {
+                Some(failure) => Verdict {
+                    in_flight: None,
+                    failure: Some(failure),
+                },
+                None => {
+                    // The backends open their directories relative to the
+                    // working directory, like the child did.
+                    let cwd = std::env::current_dir().unwrap();
+                    std::env::set_current_dir(&dir).unwrap();
+                    let verdict =
+                        panic::catch_unwind(AssertUnwindSafe(|| verify(&workload, acknowledged)))
+                            .unwrap_or_else(|_| Verdict {
+                                in_flight: None,
+                                failure: Some("reopen panicked".to_string()),
+                            });
+                    std::env::set_current_dir(cwd).unwrap();
+                    verdict
+                }
+            };
+            std::fs::remove_dir_all(&dir).unwrap();
+            let ended_at = SystemTime::now();
+
+            println!(
+                "Crash trial {} of {}: {} transactions acknowledged, {}",
+                index,
+                backend.name(),
+                acknowledged,
+                verdict.failure.as_deref().unwrap_or("passed")
+            );
+            let trial = Trial {
+                kill_after,
+                acknowledged,
+                in_flight: verdict.in_flight,
+                failure: verdict.failure,
+            };
+            recorder.record(&RunManifest {
+                run_id: &recorder.run_id(point, index),
+                backend: backend.name(),
+                revision: revision.as_deref(),
+                scenario: "crash",
+                repetition: index,
+                seed: config.seed,
+                workload: config,
+                host_id: &recorder.host_id,
+                host: &recorder.host,
+                started_at_ms: unix_ms(started_at),
+                ended_at_ms: unix_ms(ended_at),
+                result: Measurement::Crash(&trial),
+                verified: Some(trial.failure.is_none()),
+                divergence: trial.failure.as_deref(),
+            });
+            trial
+        })
+        .collect();
+    std::fs::remove_dir(&root).unwrap();
+    results
+}
+
+/// Starts the child in `dir` and kills it during the transaction after the
+/// `kill_after`-th. Returns how many transactions it acknowledged, and why
+/// the trial failed when the child died of anything but the kill or
+/// finished with an error.
+fn kill_child(
+    backend: Backend,
+    config: &WorkloadConfig,
+    dir: &Path,
+    kill_after: usize,
+    rng: &mut impl Rng,
+) -> (usize, Option<String>) {
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .arg("crash-child")
+        .args(["--backend", backend.to_possible_value().unwrap().get_name()])
+        .args(["--seed", &config.seed.to_string()])
+        .args(["--transaction-size", &config.transaction_size.to_string()])
+        .args(["--transaction-count", &config.transaction_count.to_string()])
+        .args(["--value-size", &config.value_size.to_string()])
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut acknowledged = None;
+    let mut last = Instant::now();
+    let mut interval = Duration::ZERO;
+    for line in lines.by_ref() {
+        let count: usize = line.unwrap().parse().unwrap();
+        interval = last.elapsed();
+        last = Instant::now();
+        acknowledged = Some(count);
+        if count == kill_after {
+            break;
+        }
+    }
+
+    // Lands the kill somewhere in the next `execute`, assuming it takes
+    // about as long as the previous one.
+    if acknowledged.is_some() {
+        std::thread::sleep(interval.mul_f64(rng.gen()));
+    }
+    // Fails when the child already exited, the status tells how.
+    let _ = child.kill();
+    let status = child.wait().unwrap();
+
+    // Acknowledgements printed before the kill are still in the pipe.
+    for line in lines {
+        acknowledged = Some(line.unwrap().parse().unwrap());
+    }
+
+    let failure = if acknowledged.is_none() {
+        Some(format!("child died before loading its keys, {}", status))
+    } else if status.success() || status.signal() == Some(SIGKILL) {
+        None
+    } else {
+        Some(format!("child failed, {}", status))
+    };
+    (acknowledged.unwrap_or(0), failure)
+}
+
+/// Writes every trial to `crash.csv`, with its run id and the `Host::id` of
+/// the machine, and the passed and failed trials per backend to
+/// `crash_summary.csv`, and prints the pass/fail matrix. `results` are in the
+/// order their trials were recorded in.
+pub fn write_results(
+    output_dir: &Path,
+    config: &WorkloadConfig,
+    results: &[(Backend, Vec<Trial>)],
+    recorder: &Recorder,
+) {
+    let mut wtr = csv::Writer::from_path(output_dir.join("crash.csv")).unwrap();
+    let mut header = vec!["backend", "trial", "run_id", "host"];
+    header.extend(WorkloadConfig::CSV_COLUMNS);
+    header.extend(["kill_after", "acknowledged", "in_flight", "passed", "failure"]);
+    wtr.write_record(header).unwrap();
+    for (point, (backend, trials)) in results.iter().enumerate() {
+        for (index, trial) in trials.iter().enumerate() {
+            let mut record = vec![
+                backend.name().to_string(),
+                index.to_string(),
+                recorder.run_id(point, index),
+                recorder.host_id.clone(),
+            ];
+            record.extend(config.csv_record());
+            record.extend([
+                trial.kill_after.to_string(),
+                trial.acknowledged.to_string(),
+                trial
+                    .in_flight
+                    .map(|state| format!("{:?}", state).to_lowercase())
+                    .unwrap_or_default(),
+                trial.failure.is_none().to_string(),
+                trial.failure.clone().unwrap_or_default(),
+            ]);
+            wtr.write_record(record).unwrap();
+        }
+    }
+    wtr.flush().unwrap();
+
+    let mut wtr = csv::Writer::from_path(output_dir.join("crash_summary.csv")).unwrap();
+    wtr.write_record(["backend", "trials", "passed", "failed"]).unwrap();
+    println!("Crash recovery, one column per trial (P passed, F failed):");
+    for (backend, trials) in results {
+        let passed = trials.iter().filter(|trial| trial.failure.is_none()).count();
+        wtr.write_record([
+            backend.name().to_string(),
+            trials.len().to_string(),
+            passed.to_string(),
+            (trials.len() - passed).to_string(),
+        ])
+        .unwrap();
+        let matrix = trials
+            .iter()
+            .map(|trial| if trial.failure.is_none() { 'P' } else { 'F' })
+            .collect::<String>();
+        println!("{:<20} {} {}/{}", backend.name(), matrix, passed, trials.len());
+    }
+    wtr.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Recovered::{Complete, Missing, Partial};
+
+    #[test]
+    fn classifies_what_a_transaction_left() {
+        let (a, b) = (vec![1], vec![2]);
+        let expected = [&a, &b];
+        assert_eq!(classify(&[Some(a.clone()), Some(b.clone())], &expected), Complete);
+        assert_eq!(classify(&[None, None], &expected), Missing);
+        assert_eq!(classify(&[Some(a.clone()), None], &expected), Partial);
+        // A stale value is not the transaction's write either.
+        assert_eq!(classify(&[Some(a.clone()), Some(a.clone())], &expected), Partial);
+    }
+
+    #[test]
+    fn acknowledged_transactions_have_to_survive() {
+        let passed = judge(Complete, &[Complete, Complete, Missing, Missing], 2);
+        assert_eq!((passed.in_flight, passed.failure), (Some(Missing), None));
+        let passed = judge(Complete, &[Complete, Complete, Complete, Missing], 2);
+        assert_eq!((passed.in_flight, passed.failure), (Some(Complete), None));
+        // Every transaction acknowledged, nothing was in flight.
+        let finished = judge(Complete, &[Complete, Complete], 2);
+        assert_eq!((finished.in_flight, finished.failure), (None, None));
+
+        let lost = judge(Complete, &[Complete, Missing, Missing], 2);
+        assert_eq!(lost.failure.as_deref(), Some("transaction 1 is Missing"));
+        let torn = judge(Complete, &[Complete, Partial, Missing], 1);
+        assert_eq!(torn.failure.as_deref(), Some("transaction 1 is Partial"));
+        let early = judge(Complete, &[Complete, Missing, Complete], 1);
+        assert_eq!(early.failure.as_deref(), Some("transaction 2 is Complete"));
+        let preload = judge(Partial, &[Complete], 1);
+        assert_eq!(preload.failure.as_deref(), Some("preload lost"));
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_THETA: f64 = 0.99;
+const DEFAULT_HOT_FRACTION: f64 = 0.2;
+const DEFAULT_HOT_OPERATION_FRACTION: f64 = 0.8;
+/// Draws `next_unused` makes before walking to the closest unused key.
+const MAX_REDRAWS: usize = 64;
+
+/// How the key of a read or write is picked among the keys in the table.
+///
+/// Written as `name[:parameter...]` on the command line and in scenarios, e.g.
+/// `uniform`, `zipfian:0.9` or `hotspot:0.1:0.9`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum KeyDistribution {
+    /// Reads walk the keys in insertion order, writes insert new keys.
+    Sequential,
+    Uniform,
+    /// Popular keys follow a zipfian law with exponent `theta`, scattered over
+    /// the key space.
+    Zipfian { theta: f64 },
+    /// `hot_operation_fraction` of the operations go to the first
+    /// `hot_fraction` of the keys.
+    Hotspot {
+        hot_fraction: f64,
+        hot_operation_fraction: f64,
+    },
+    /// Recently inserted keys are the most popular, following a zipfian law.
+    Latest { theta: f64 },
+}
+
+impl FromStr for KeyDistribution {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.split(':');
+        let name = parts.next().unwrap_or_default();
+        let parameters = parts
+            .map(|part| {
+                part.parse::<f64>()
+                    .map_err(|_| format!("Invalid parameter {} in key distribution {}", part, value))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let parameter = |index: usize, default: f64| parameters.get(index).copied().unwrap_or(default);
+
+        let (distribution, expected_parameters) = match name {
+            "sequential" => (KeyDistribution::Sequential, 0),
+            "uniform" => (KeyDistribution::Uniform, 0),
+            "zipfian" => (
+                KeyDistribution::Zipfian {
+                    theta: parameter(0, DEFAULT_THETA),
+                },
+                1,
+            ),
+            "hotspot" => (
+                KeyDistribution::Hotspot {
+                    hot_fraction: parameter(0, DEFAULT_HOT_FRACTION),
+                    hot_operation_fraction: parameter(1, DEFAULT_HOT_OPERATION_FRACTION),
+                },
+                2,
+            ),
+            "latest" => (
+                KeyDistribution::Latest {
+                    theta: parameter(0, DEFAULT_THETA),
+                },
+                1,
+            ),
+            _ => return Err(format!("Unknown key distribution {}", value)),
+        };
+
+        if parameters.len() > expected_parameters {
+            return Err(format!("Too many parameters in key distribution {}", value));
+        }
+        match distribution {
+            KeyDistribution::Zipfian { theta } | KeyDistribution::Latest { theta }
+                if !(theta > 0.0 && theta < 1.0) =>
+            {
+                Err(format!("Zipfian theta must be between 0 and 1 (exclusive), got {}", theta))
+            }
+            KeyDistribution::Hotspot {
+                hot_fraction,
+                hot_operation_fraction,
+            } if !(0.0..=1.0).contains(&hot_fraction)
+                || !(0.0..=1.0).contains(&hot_operation_fraction) =>
+            {
+                Err(format!("Hotspot fractions must be between 0 and 1, got {}", value))
+            }
+            distribution => Ok(distribution),
+        }
+    }
+}
+
+impl TryFrom<String> for KeyDistribution {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<KeyDistribution> for String {
+    fn from(value: KeyDistribution) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for KeyDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyDistribution::Sequential => write!(f, "sequential"),
+            KeyDistribution::Uniform => write!(f, "uniform"),
+            KeyDistribution::Zipfian { theta } => write!(f, "zipfian:{}", theta),
+            KeyDistribution::Hotspot {
+                hot_fraction,
+                hot_operation_fraction,
+            } => write!(f, "hotspot:{}:{}", hot_fraction, hot_operation_fraction),
+            KeyDistribution::Latest { theta } => write!(f, "latest:{}", theta),
+        }
+    }
+}
+
+/// Draws key indices following a `KeyDistribution`. Indices count the keys
+/// in insertion order, so `key_count` is the index of the next new key.
+pub struct KeyChooser {
+    distribution: KeyDistribution,
+    /// Next key of a sequential read chooser.
+    next: usize,
+    /// Whether this chooses keys for writes, which insert new keys when
+    /// sequential.
+    writes: bool,
+    zipfian: Option<Zipfian>,
+}
+
+impl KeyChooser {
+    pub fn for_reads(distribution: KeyDistribution) -> Self {
+        Self::new(distribution, false)
+    }
+
+    pub fn for_writes(distribution: KeyDistribution) -> Self {
+        Self::new(distribution, true)
+    }
+
+    fn new(distribution: KeyDistribution, writes: bool) -> Self {
+        let zipfian = match distribution {
+            KeyDistribution::Zipfian { theta } | KeyDistribution::Latest { theta } => {
+                Some(Zipfian::new(theta))
+            }
+            _ => None,
+        };
+        KeyChooser {
+            distribution,
+            next: 0,
+            writes,
+            zipfian,
+        }
+    }
+
+    /// Picks the next key index. Only a sequential write chooser returns
+    /// `key_count`, meaning a new key, everything else picks an existing key.
+    pub fn next(&mut self, rng: &mut impl Rng, key_count: usize) -> usize {
+        assert!(key_count > 0, "Cannot pick a key from an empty table");
+
+        match self.distribution {
+            KeyDistribution::Sequential if self.writes => key_count,
+            KeyDistribution::Sequential => {
+                let index = self.next % key_count;
+                self.next += 1;
+                index
+            }
+            KeyDistribution::Uniform => rng.gen_range(0..key_count),
+            KeyDistribution::Zipfian { .. } => {
+                let rank = self.zipfian.as_mut().unwrap().next(rng, key_count);
+                (fnv_hash(rank as u64) % key_count as u64) as usize
+            }
+            KeyDistribution::Hotspot {
+                hot_fraction,
+                hot_operation_fraction,
+            } => {
+                let hot_count = ((key_count as f64 * hot_fraction) as usize).clamp(1, key_count);
+                if hot_count == key_count || rng.gen_bool(hot_operation_fraction) {
+                    rng.gen_range(0..hot_count)
+                } else {
+                    rng.gen_range(hot_count..key_count)
+                }
+            }
+            KeyDistribution::Latest { .. } => {
+                key_count - 1 - self.zipfian.as_mut().unwrap().next(rng, key_count)
+            }
+        }
+    }
+
+    /// Like `next`, but never picks a key in `used`, as a transaction may
+    /// touch every key only once. Random choosers draw again, sequential
+    /// reads step past the used keys.
+    pub fn next_unused(
+        &mut self,
+        rng: &mut impl Rng,
+        key_count: usize,
+        used: &HashSet<usize>,
+    ) -> usize {
+        let inserts = self.writes && self.distribution == KeyDistribution::Sequential;
+        if !inserts && used.len() >= key_count {
+            panic!(
+                "Transactions touch more than the {} keys in the table, raise the record count",
+                key_count
+            );
+        }
+
+        let mut index = self.next(rng, key_count);
+        for _ in 0..MAX_REDRAWS {
+            if !used.contains(&index) {
+                return index;
+            }
+            index = self.next(rng, key_count);
+        }
+        while used.contains(&index) {
+            index = (index + 1) % key_count;
+        }
+        index
+    }
+}
+
+/// Zipfian generator from Gray et al., "Quickly Generating Billion-Record
+/// Synthetic Databases", as used by YCSB. The zeta constant is extended
+/// incrementally when the number of items grows.
+struct Zipfian {
+    theta: f64,
+    alpha: f64,
+    zeta2: f64,
+    item_count: usize,
+    zeta_n: f64,
+}
+
+impl Zipfian {
+    fn new(theta: f64) -> Self {
+        Zipfian {
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta2: 1.0 + 0.5f64.powf(theta),
+            item_count: 0,
+            zeta_n: 0.0,
+        }
+    }
+
+    /// Returns a rank in `0..item_count`, rank 0 being the most popular.
+    fn next(&mut self, rng: &mut impl Rng, item_count: usize) -> usize {
+        while self.item_count < item_count {
+            self.item_count += 1;
+            self.zeta_n += 1.0 / (self.item_count as f64).powf(self.theta);
+        }
+
+        let n = item_count as f64;
+        let eta = (1.0 - (2.0 / n).powf(1.0 - self.theta)) / (1.0 - self.zeta2 / self.zeta_n);
+
+        let u: f64 = rng.gen();
+        let uz = u * self.zeta_n;
+        let rank = if uz < 1.0 {
+            0
+        } else if uz < self.zeta2 {
+            1
+        } else {
+            (n * (eta * u - eta + 1.0).powf(self.alpha)) as usize
+        };
+        rank.min(item_count - 1)
+    }
+}
+
+/// FNV-1a over the bytes of `value`, spreads popular zipfian ranks over the
+/// whole key space.
+fn fnv_hash(value: u64) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in value.to_le_bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    const KEYS: usize = 1000;
+    const DRAWS: usize = 20000;
+
+    /// How often each key index was drawn.
+    fn histogram(distribution: &str) -> Vec<usize> {
+        let mut chooser = KeyChooser::for_reads(distribution.parse().unwrap());
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut counts = vec![0; KEYS];
+        for _ in 0..DRAWS {
+            counts[chooser.next(&mut rng, KEYS)] += 1;
+        }
+        counts
+    }
+
+    fn share(counts: &[usize]) -> f64 {
+        counts.iter().sum::<usize>() as f64 / DRAWS as f64
+    }
+
+    #[test]
+    fn sequential_reads_wrap_and_writes_insert() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut reads = KeyChooser::for_reads(KeyDistribution::Sequential);
+        let indices = (0..5).map(|_| reads.next(&mut rng, 3)).collect::<Vec<_>>();
+        assert_eq!(indices, [0, 1, 2, 0, 1]);
+
+        let mut writes = KeyChooser::for_writes(KeyDistribution::Sequential);
+        assert_eq!(writes.next(&mut rng, 3), 3);
+    }
+
+    #[test]
+    fn hotspot_sends_its_share_to_the_hot_keys() {
+        let counts = histogram("hotspot:0.1:0.9");
+        assert!((share(&counts[..KEYS / 10]) - 0.9).abs() < 0.02);
+    }
+
+    #[test]
+    fn zipfian_concentrates_on_few_scattered_keys() {
+        let uniform = histogram("uniform");
+        let zipfian = histogram("zipfian:0.99");
+        let top = |counts: &[usize]| *counts.iter().max().unwrap() as f64 / DRAWS as f64;
+        assert!(top(&uniform) < 0.01);
+        assert!(top(&zipfian) > 0.05);
+        // Popular keys are hashed over the key space, not the first ones.
+        assert!(share(&zipfian[..KEYS / 10]) < 0.5);
+    }
+
+    #[test]
+    fn latest_favours_the_newest_keys() {
+        let counts = histogram("latest:0.99");
+        let newest = counts
+            .iter()
+            .enumerate()
+            .max_by_key(|&(_, count)| count)
+            .unwrap()
+            .0;
+        assert_eq!(newest, KEYS - 1);
+        assert!(share(&counts[KEYS - KEYS / 10..]) > 0.5);
+    }
+
+    #[test]
+    fn next_unused_skips_used_keys() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut chooser = KeyChooser::for_reads("zipfian".parse().unwrap());
+        // Only key 3 is left, the redraws hardly ever hit it and the walk does.
+        let used = (0..KEYS)
+            .filter(|&index| index != 3)
+            .collect::<HashSet<_>>();
+        assert_eq!(chooser.next_unused(&mut rng, KEYS, &used), 3);
+
+        let mut reads = KeyChooser::for_reads(KeyDistribution::Sequential);
+        let used = HashSet::from([0, 1]);
+        assert_eq!(reads.next_unused(&mut rng, 3, &used), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "raise the record count")]
+    fn next_unused_panics_when_every_key_is_used() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let used = (0..3).collect::<HashSet<_>>();
+        KeyChooser::for_reads(KeyDistribution::Uniform).next_unused(&mut rng, 3, &used);
+    }
+
+    #[test]
+    fn parses_parameters_with_defaults() {
+        assert_eq!(
+            "hotspot:0.1".parse(),
+            Ok(KeyDistribution::Hotspot {
+                hot_fraction: 0.1,
+                hot_operation_fraction: DEFAULT_HOT_OPERATION_FRACTION,
+            })
+        );
+        assert!("zipfian:1".parse::<KeyDistribution>().is_err());
+        assert!("uniform:0.5".parse::<KeyDistribution>().is_err());
+    }
+}
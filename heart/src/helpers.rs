@@ -1,49 +1,124 @@
-use std::{thread, sync::mpsc::{self, Sender}};
+use std::{path::Path, thread, sync::mpsc::{self, Sender}, time::{Instant, SystemTime}};
 
-use sysinfo::{System, SystemExt, CpuExt};
-use rand::Rng;
+use sysinfo::{System, SystemExt, CpuExt, ProcessExt, ProcessRefreshKind};
+
+use crate::arrival::ArrivalProcess;
+use crate::backend::Backend;
+use crate::manifest::{self, unix_ms, Measurement, Recorder, RunManifest};
+use crate::metrics::{IoCounters, LatencySummary, ProcessCpu, RunResult};
+use crate::stats::Summary;
+use crate::workload::WorkloadConfig;
+
+/// Runs one benchmark and returns what it measured.
+pub type TestFunction = fn(&WorkloadConfig, &RunOptions, &Sender<CPUStatsCommand>) -> RunResult;
+
+pub const DEFAULT_WARMUP: usize = 1;
+pub const DEFAULT_REPETITIONS: usize = 3;
+pub const DEFAULT_CV_THRESHOLD: f64 = 0.05;
+/// Share of the offered load an open loop has to achieve to count as keeping up.
+pub const SATURATION_FRACTION: f64 = 0.95;
+
+/// How every configuration of a sweep is measured and where the results go.
+#[derive(Clone, Copy)]
+pub struct RunOptions<'a> {
+    pub output_dir: &'a Path,
+    /// Runs per configuration that are thrown away.
+    pub warmup: usize,
+    /// Measured runs per configuration.
+    pub repetitions: usize,
+    /// Coefficient of variation above which a configuration is flagged as unstable.
+    pub cv_threshold: f64,
+    /// Whether every measured run is checked against a `HashMap` before its
+    /// backend is torn down, see `verify`.
+    pub verify: bool,
+    /// SQLite database every measured run is appended to, see `History`.
+    pub history: &'a Path,
+}
 
 pub enum CPUStatsCommand {
+    /// Labels the samples taken from now on with the id of the next run.
+    Run(String),
     Start,
     Stop,
     Abort,
 }
 
-
-pub fn with_percentage_true(x: i32) -> bool {
-    if !(0.0..=100.0).contains(&(x as f64)) {
-        panic!("Percentage must be between 0 and 100");
-    }
-
-    let rand_value: f64 = rand::thread_rng().gen_range(0.0..100.0);
-    rand_value < x.into()
-}
-
-fn read_and_store_cpu_stats(rx: mpsc::Receiver<(CPUStatsCommand)>, test_name: String) -> impl FnOnce() {
+/// Samples per-core CPU usage and this process's CPU time and thread count
+/// into `{test_name}_cpu_stats.csv` and, at the same instants, this process's
+/// memory and the memory still available on the system into
+/// `{test_name}_mem_stats.csv`. All memory values are bytes. The cumulative
+/// `/proc/self/io` counters go to `{test_name}_io_stats.csv`.
+///
+/// Every row starts with the id of the run it was taken during, as in the
+/// results CSV, and the microseconds since the sampler started.
+fn read_and_store_cpu_stats(rx: mpsc::Receiver<CPUStatsCommand>, output_dir: &Path, test_name: &str) -> impl FnOnce() {
+    let path = output_dir.join(format!("{}_cpu_stats.csv", test_name));
+    let mem_path = output_dir.join(format!("{}_mem_stats.csv", test_name));
+    let io_path = output_dir.join(format!("{}_io_stats.csv", test_name));
     move || {
         let mut sys = System::new();
+        let pid = sysinfo::get_current_pid().unwrap();
+        let epoch = Instant::now();
 
         // create csv file to store cpu stats
-        let mut wtr = csv::Writer::from_path(format!("results/{}_cpu_stats.csv", test_name)).unwrap();
+        let mut wtr = csv::Writer::from_path(path).unwrap();
+        let mut mem_wtr = csv::Writer::from_path(mem_path).unwrap();
+        let mut io_wtr = csv::Writer::from_path(io_path).unwrap();
 
         sys.refresh_cpu(); // Refreshing CPU information.
 
+        let prefix = ["run_id", "timestamp_us"];
         // write column for each cpu
-        wtr.write_record(sys.cpus().iter().map(|x| x.name()).collect::<Vec<_>>()).unwrap();
+        let mut header = prefix.to_vec();
+        header.extend(ProcessCpu::CSV_COLUMNS);
+        header.extend(sys.cpus().iter().map(|x| x.name()));
+        wtr.write_record(header).unwrap();
+        mem_wtr.write_record(prefix.iter().chain(&["rss", "virtual_memory", "available_memory"])).unwrap();
+        io_wtr.write_record(prefix.iter().chain(&IoCounters::CSV_COLUMNS)).unwrap();
 
         let mut take_stats = false;
+        let mut run_id = String::new();
 
-        loop {
-            match rx.try_recv() { 
-                Ok(CPUStatsCommand::Start) => take_stats = true,
-                Ok(CPUStatsCommand::Stop) | Err(mpsc::TryRecvError::Disconnected) => take_stats = false,
-                Ok(CPUStatsCommand::Abort) => break,
-                Err(mpsc::TryRecvError::Empty) => (),
+        'sampling: loop {
+            // Every pending command, a run can be shorter than a tick and its
+            // samples must not carry the id of the one before.
+            loop {
+                match rx.try_recv() {
+                    Ok(CPUStatsCommand::Run(id)) => run_id = id,
+                    Ok(CPUStatsCommand::Start) => take_stats = true,
+                    Ok(CPUStatsCommand::Stop) => take_stats = false,
+                    Ok(CPUStatsCommand::Abort) => break 'sampling,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        take_stats = false;
+                        break;
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                }
             }
 
             if take_stats {
+                let prefix = [run_id.clone(), epoch.elapsed().as_micros().to_string()];
+
                 sys.refresh_cpu(); // Refreshing CPU information.
-                wtr.write_record(sys.cpus().iter().map(|cpu| cpu.cpu_usage().to_string()).collect::<Vec<_>>()).unwrap();
+                let mut record = prefix.to_vec();
+                match ProcessCpu::read() {
+                    Some(process) => record.extend(process.csv_record()),
+                    None => record.extend(ProcessCpu::CSV_COLUMNS.map(|_| String::new())),
+                }
+                record.extend(sys.cpus().iter().map(|cpu| cpu.cpu_usage().to_string()));
+                wtr.write_record(record).unwrap();
+
+                sys.refresh_process_specifics(pid, ProcessRefreshKind::new());
+                sys.refresh_memory();
+                let (rss, virtual_memory) = sys
+                    .process(pid)
+                    .map(|process| (process.memory(), process.virtual_memory()))
+                    .unwrap_or_default();
+                mem_wtr.write_record(prefix.iter().cloned().chain([rss, virtual_memory, sys.available_memory()].map(|x| x.to_string()))).unwrap();
+
+                if let Some(io) = IoCounters::read() {
+                    io_wtr.write_record(prefix.iter().cloned().chain(io.csv_record())).unwrap();
+                }
             }
 
             // Sleeping to let time for the system to run for long
@@ -52,123 +127,382 @@ fn read_and_store_cpu_stats(rx: mpsc::Receiver<(CPUStatsCommand)>, test_name: St
         }
 
         wtr.flush().unwrap();
+        mem_wtr.flush().unwrap();
+        io_wtr.flush().unwrap();
     }
 }
 
-fn write_to_csv(results: Vec<(u128, i32, usize, usize)>, file_name: &str) {
-    let mut wtr = csv::Writer::from_path(format!("results/{}.csv", file_name)).unwrap();
-    wtr.write_record(["duration", "write_percentage", "transaction_size", "transaction_count"]).unwrap();
-    for (duration, write_percentage, transaction_size, transaction_count) in results {
-        wtr.serialize((
-            duration,
-            write_percentage,
-            transaction_size,
-            transaction_count,
-        ))
-        .unwrap();
+/// Writes every measured run to `{file_name}.csv`, with the `Host::id` of the
+/// machine it ran on.
+fn write_to_csv(
+    results: &[(WorkloadConfig, Vec<RunResult>)],
+    output_dir: &Path,
+    file_name: &str,
+    recorder: &Recorder,
+) {
+    let mut wtr = csv::Writer::from_path(output_dir.join(format!("{}.csv", file_name))).unwrap();
+    let mut header = vec!["duration"];
+    header.extend(WorkloadConfig::CSV_COLUMNS);
+    header.extend(["repetition", "run_id", "host"]);
+    header.extend(RunResult::CSV_COLUMNS);
+    wtr.write_record(header).unwrap();
+    for (point, (config, runs)) in results.iter().enumerate() {
+        for (repetition, result) in runs.iter().enumerate() {
+            let mut record = vec![result.duration.to_string()];
+            record.extend(config.csv_record());
+            record.push(repetition.to_string());
+            record.push(recorder.run_id(point, repetition));
+            record.push(recorder.host_id.clone());
+            record.extend(result.csv_record());
+            wtr.write_record(record).unwrap();
+        }
     }
     wtr.flush().unwrap();
 }
 
-pub fn create_percentage_test(function_under_test: fn(i32, usize, usize, &Sender<CPUStatsCommand>) -> u128, file_name: &str) {
-    println!("Running percentage test with fn {}", file_name);
-    let (tx, rx) = mpsc::channel();
-    thread::spawn(read_and_store_cpu_stats(rx, file_name.to_string()));
-    let mut results = Vec::<(u128, i32, usize, usize)>::new();
-    for write_percentage in (0..100).step_by(10) {
-        results.push((
-            function_under_test(write_percentage, 100000, 100, &tx),
-            write_percentage,
-            100000,
-            100,
-        ));
+/// Writes the latency of every worker thread of every run to `{file_name}_threads.csv`.
+fn write_thread_latencies_to_csv(
+    results: &[(WorkloadConfig, Vec<RunResult>)],
+    output_dir: &Path,
+    file_name: &str,
+    recorder: &Recorder,
+) {
+    let path = output_dir.join(format!("{}_threads.csv", file_name));
+    let mut wtr = csv::Writer::from_path(path).unwrap();
+    wtr.write_record(["run_id", "threads", "thread", "p50_us", "p90_us", "p99_us", "p999_us", "max_us"])
+        .unwrap();
+    for (point, (config, runs)) in results.iter().enumerate() {
+        for (repetition, result) in runs.iter().enumerate() {
+            for (thread, latency) in result.thread_latencies.iter().enumerate() {
+                let mut record = vec![
+                    recorder.run_id(point, repetition),
+                    config.threads.to_string(),
+                    thread.to_string(),
+                ];
+                record.extend(
+                    [latency.p50, latency.p90, latency.p99, latency.p999, latency.max]
+                        .map(|x| x.to_string()),
+                );
+                wtr.write_record(record).unwrap();
+            }
+        }
     }
-    tx.send(CPUStatsCommand::Abort).unwrap();
-
-    write_to_csv(results, file_name);
+    wtr.flush().unwrap();
 }
 
-pub fn create_transaction_size_test(
-    function_under_test: fn(i32, usize, usize, &Sender<CPUStatsCommand>) -> u128,
+/// Writes one row per configuration with statistics over its repetitions.
+fn write_summary_to_csv(
+    results: &[(WorkloadConfig, Vec<RunResult>)],
+    options: &RunOptions,
     file_name: &str,
 ) {
-    const NUMBER_OF_OPERATIONS_POWER: u32 = 7;
+    let path = options.output_dir.join(format!("{}_summary.csv", file_name));
+    let mut wtr = csv::Writer::from_path(path).unwrap();
+    let mut header = WorkloadConfig::CSV_COLUMNS.to_vec();
+    header.extend([
+        "repetitions",
+        "mean_ms",
+        "median_ms",
+        "stddev_ms",
+        "ci95_low_ms",
+        "ci95_high_ms",
+        "cv",
+        "unstable",
+        "failed_runs",
+    ]);
+    wtr.write_record(header).unwrap();
+    for (config, runs) in results {
+        // Fast runs take a few milliseconds, whole ones would make the
+        // spread mostly rounding.
+        let durations = runs
+            .iter()
+            .map(|result| result.duration_secs * 1000.0)
+            .collect::<Vec<_>>();
+        let summary = Summary::of(&durations);
+        let unstable = summary.cv > options.cv_threshold;
+        if unstable {
+            println!(
+                "Unstable point in {}: {} has a coefficient of variation of {:.3}",
+                file_name, config, summary.cv
+            );
+        }
+        let mut record = config.csv_record();
+        record.extend([
+            summary.count.to_string(),
+            summary.mean.to_string(),
+            summary.median.to_string(),
+            summary.stddev.to_string(),
+            summary.ci95_low.to_string(),
+            summary.ci95_high.to_string(),
+            summary.cv.to_string(),
+            unstable.to_string(),
+            runs.iter().filter(|result| result.failed()).count().to_string(),
+        ]);
+        wtr.write_record(record).unwrap();
+    }
+    wtr.flush().unwrap();
+}
+
+/// Runs `backend` for every configuration, `options.warmup` times without and
+/// `options.repetitions` times with measuring, while sampling CPU and memory
+/// usage. Writes a manifest per run to `{file_name}.jsonl` and appends it to
+/// `options.history`, and writes the single runs, their per-thread latencies,
+/// their summary and the samples as CSVs to `options.output_dir`. Returns the
+/// runs of every configuration.
+pub fn run_points(
+    backend: Backend,
+    points: Vec<WorkloadConfig>,
+    options: &RunOptions,
+    file_name: &str,
+) -> Vec<(WorkloadConfig, Vec<RunResult>)> {
+    let function_under_test = crate::test_function(backend);
+    let revision = manifest::revision(backend);
+    let recorder = Recorder::create(options.output_dir, file_name, options.history);
 
-    println!("Running transaction size test with fn {}", file_name);
     let (tx, rx) = mpsc::channel();
-    thread::spawn(read_and_store_cpu_stats(rx, file_name.to_string()));
-    let mut results = Vec::<(u128, i32, usize, usize)>::new();
-    for i in 2..NUMBER_OF_OPERATIONS_POWER {
-        results.push((
-            function_under_test(
-                10,
-                usize::pow(10, i),
-                usize::pow(10, NUMBER_OF_OPERATIONS_POWER - i),
-                &tx,
-            ),
-            10,
-            usize::pow(10, i),
-            usize::pow(10, NUMBER_OF_OPERATIONS_POWER - i),
-        ));
+    let cpu_stats = thread::spawn(read_and_store_cpu_stats(rx, options.output_dir, file_name));
+
+    // Warmup runs report to a channel nobody samples for and are not verified.
+    let (warmup_tx, _warmup_rx) = mpsc::channel();
+    let warmup_options = RunOptions {
+        verify: false,
+        ..*options
+    };
+
+    let mut results = Vec::<(WorkloadConfig, Vec<RunResult>)>::new();
+    for (point, config) in points.into_iter().enumerate() {
+        for _ in 0..options.warmup {
+            function_under_test(&config, &warmup_options, &warmup_tx);
+        }
+        let runs = (0..options.repetitions)
+            .map(|repetition| {
+                let id = recorder.run_id(point, repetition);
+                tx.send(CPUStatsCommand::Run(id.clone())).unwrap();
+                let started_at = SystemTime::now();
+                let result = function_under_test(&config, options, &tx);
+                let ended_at = SystemTime::now();
+                if let Some(Err(divergence)) = &result.verification {
+                    println!("Run {} failed verification: {}", id, divergence);
+                }
+                let manifest = RunManifest {
+                    run_id: &id,
+                    backend: backend.name(),
+                    revision: revision.as_deref(),
+                    scenario: file_name,
+                    repetition,
+                    seed: config.seed,
+                    workload: &config,
+                    host_id: &recorder.host_id,
+                    host: &recorder.host,
+                    started_at_ms: unix_ms(started_at),
+                    ended_at_ms: unix_ms(ended_at),
+                    result: Measurement::Benchmark(&result),
+                    verified: result.verification.as_ref().map(Result::is_ok),
+                    divergence: result
+                        .verification
+                        .as_ref()
+                        .and_then(|verification| verification.as_ref().err())
+                        .map(String::as_str),
+                };
+                recorder.record(&manifest);
+                result
+            })
+            .collect();
+        results.push((config, runs));
     }
     tx.send(CPUStatsCommand::Abort).unwrap();
+    cpu_stats.join().unwrap();
+
+    write_to_csv(&results, options.output_dir, file_name, &recorder);
+    write_thread_latencies_to_csv(&results, options.output_dir, file_name, &recorder);
+    write_summary_to_csv(&results, options, file_name);
+    results
+}
 
-    write_to_csv(results, file_name);
+/// Sweeps the write percentage over what `base` leaves to writes and reads
+/// after its deletes and read-modify-writes.
+pub fn create_percentage_test(
+    backend: Backend,
+    options: &RunOptions,
+    file_name: &str,
+    base: &WorkloadConfig,
+    step: usize,
+) {
+    println!("Running percentage test with fn {}", file_name);
+    let max_write_percentage = base.write_percentage + base.read_percentage();
+    let points = (0..max_write_percentage)
+        .step_by(step)
+        .map(|write_percentage| WorkloadConfig {
+            write_percentage,
+            ..base.clone()
+        })
+        .collect();
+    run_points(backend, points, options, file_name);
 }
 
+/// Keeps the total number of operations at `10^total_power` while moving them
+/// from many small transactions to few large ones.
+pub fn create_transaction_size_test(
+    backend: Backend,
+    options: &RunOptions,
+    file_name: &str,
+    base: &WorkloadConfig,
+    min_power: u32,
+    total_power: u32,
+) {
+    println!("Running transaction size test with fn {}", file_name);
+    let points = (min_power..total_power)
+        .map(|i| WorkloadConfig {
+            transaction_size: usize::pow(10, i),
+            transaction_count: usize::pow(10, total_power - i),
+            ..base.clone()
+        })
+        .collect();
+    run_points(backend, points, options, file_name);
+}
 
 pub fn create_simple_test(
-    function_under_test: fn(i32, usize, usize, &Sender<CPUStatsCommand>) -> u128,
+    backend: Backend,
+    options: &RunOptions,
     file_name: &str,
+    base: &WorkloadConfig,
 ) {
+    println!("Running simple test with fn {}", file_name);
+    run_points(backend, vec![base.clone()], options, file_name);
+}
 
-    println!("Running transaction size test with fn {}", file_name);
-    let (tx, rx) = mpsc::channel();
-    thread::spawn(read_and_store_cpu_stats(rx, file_name.to_string()));
-    let mut results = Vec::<(u128, i32, usize, usize)>::new();
-
-        results.push((
-            function_under_test(
-                10,
-                usize::pow(10, 5),
-                usize::pow(10, 2),
-                &tx,
-            ),
-            10,
-            usize::pow(10, 5),
-            usize::pow(10, 2),
-        ));
-    
-    tx.send(CPUStatsCommand::Abort).unwrap();
+/// Grows the transaction size from `10^min_power` up to (excluding) `10^max_power`
+/// at a fixed transaction count.
+pub fn create_transaction_big_size_test(
+    backend: Backend,
+    options: &RunOptions,
+    file_name: &str,
+    base: &WorkloadConfig,
+    min_power: u32,
+    max_power: u32,
+) {
+    println!("Running transaction big size test with fn {}", file_name);
+    let points = (min_power..max_power)
+        .map(|i| WorkloadConfig {
+            transaction_size: usize::pow(10, i),
+            ..base.clone()
+        })
+        .collect();
+    run_points(backend, points, options, file_name);
+}
 
-    write_to_csv(results, file_name);
+pub fn create_value_size_test(
+    backend: Backend,
+    options: &RunOptions,
+    file_name: &str,
+    base: &WorkloadConfig,
+    value_sizes: &[usize],
+) {
+    println!("Running value size test with fn {}", file_name);
+    let points = value_sizes
+        .iter()
+        .map(|&value_size| WorkloadConfig {
+            value_size,
+            ..base.clone()
+        })
+        .collect();
+    run_points(backend, points, options, file_name);
 }
 
+pub fn create_thread_test(
+    backend: Backend,
+    options: &RunOptions,
+    file_name: &str,
+    base: &WorkloadConfig,
+    thread_counts: &[usize],
+) {
+    println!("Running thread test with fn {}", file_name);
+    let points = thread_counts
+        .iter()
+        .map(|&threads| WorkloadConfig {
+            threads,
+            ..base.clone()
+        })
+        .collect();
+    run_points(backend, points, options, file_name);
+}
 
-pub fn create_transaction_big_size_test(
-    function_under_test: fn(i32, usize, usize, &Sender<CPUStatsCommand>) -> u128,
+/// Offers `rates` operations per second, arriving as `process` does, and
+/// writes the achieved throughput and the latency from the intended start at
+/// every rate to `{file_name}_load_curve.csv`. A rate is saturated when less
+/// than `SATURATION_FRACTION` of it is achieved.
+pub fn create_offered_load_test(
+    backend: Backend,
+    options: &RunOptions,
     file_name: &str,
+    base: &WorkloadConfig,
+    process: ArrivalProcess,
+    rates: &[f64],
 ) {
-    const NUMBER_OF_OPERATIONS_POWER: u32 = 8;
+    println!("Running offered load test with fn {}", file_name);
+    let points = rates
+        .iter()
+        .map(|&rate| WorkloadConfig {
+            arrival: process.at(rate),
+            ..base.clone()
+        })
+        .collect();
+    let results = run_points(backend, points, options, file_name);
 
-    println!("Running transaction size test with fn {}", file_name);
-    let (tx, rx) = mpsc::channel();
-    thread::spawn(read_and_store_cpu_stats(rx, file_name.to_string()));
-    let mut results = Vec::<(u128, i32, usize, usize)>::new();
-    for i in 4..NUMBER_OF_OPERATIONS_POWER {
-        results.push((
-            function_under_test(
-                10,
-                usize::pow(10, i),
-                100,
-                &tx,
-            ),
-            10,
-            usize::pow(10, i),
-            100,
-        ));
+    let path = options.output_dir.join(format!("{}_load_curve.csv", file_name));
+    let mut wtr = csv::Writer::from_path(path).unwrap();
+    let mut header = WorkloadConfig::CSV_COLUMNS.to_vec();
+    header.extend([
+        "repetitions",
+        "achieved_ops_per_sec",
+        "p50_us",
+        "p90_us",
+        "p99_us",
+        "p999_us",
+        "max_us",
+        "saturated",
+    ]);
+    wtr.write_record(header).unwrap();
+
+    let mut saturation = None;
+    for (config, runs) in &results {
+        let offered = config.arrival.offered_load().unwrap();
+        let throughputs = runs.iter().map(|result| result.throughput).collect::<Vec<_>>();
+        let achieved = Summary::of(&throughputs).mean;
+        let saturated = achieved < SATURATION_FRACTION * offered;
+        if saturated && saturation.is_none() {
+            saturation = Some((offered, achieved));
+        }
+
+        // Median over the repetitions of every percentile.
+        let percentile = |f: fn(&LatencySummary) -> u64| {
+            let values = runs.iter().map(|result| f(&result.latency) as f64).collect::<Vec<_>>();
+            Summary::of(&values).median.to_string()
+        };
+        let mut record = config.csv_record();
+        record.extend([
+            runs.len().to_string(),
+            achieved.to_string(),
+            percentile(|x| x.p50),
+            percentile(|x| x.p90),
+            percentile(|x| x.p99),
+            percentile(|x| x.p999),
+            percentile(|x| x.max),
+            saturated.to_string(),
+        ]);
+        wtr.write_record(record).unwrap();
     }
-    tx.send(CPUStatsCommand::Abort).unwrap();
+    wtr.flush().unwrap();
 
-    write_to_csv(results, file_name);
+    match saturation {
+        Some((offered, achieved)) => println!(
+            "{} saturates at {} offered ops/sec, achieving {:.0}",
+            file_name, offered, achieved
+        ),
+        None => println!(
+            "{} did not saturate up to {} offered ops/sec",
+            file_name,
+            rates.iter().copied().fold(0.0, f64::max)
+        ),
+    }
 }
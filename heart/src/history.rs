@@ -0,0 +1,200 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection, ToSql};
+
+use crate::manifest::RunManifest;
+
+/// Database every measured run is appended to unless `--history` says otherwise.
+pub const DEFAULT_HISTORY_PATH: &str = "heart-history.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_id TEXT NOT NULL UNIQUE,
+    backend TEXT NOT NULL,
+    scenario TEXT NOT NULL,
+    revision TEXT,
+    host_id TEXT NOT NULL,
+    started_at_ms INTEGER NOT NULL,
+    ended_at_ms INTEGER NOT NULL,
+    duration_ms INTEGER,
+    ops_per_sec REAL,
+    p50_us INTEGER,
+    p99_us INTEGER,
+    verified INTEGER,
+    manifest TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS runs_key ON runs (backend, scenario, revision);
+";
+
+/// Columns of `StoredRun`, in order.
+const COLUMNS: &str = "run_id, backend, scenario, revision, host_id, started_at_ms, \
+    ended_at_ms, duration_ms, ops_per_sec, p50_us, p99_us, verified, manifest";
+
+/// The local SQLite database keeping every measured run across invocations.
+pub struct History(Connection);
+
+/// A run read back from the history. Measurements the run's test does not
+/// take, like the latency of a crash trial, are `None`.
+pub struct StoredRun {
+    pub run_id: String,
+    pub backend: String,
+    pub scenario: String,
+    pub revision: Option<String>,
+    pub host_id: String,
+    pub started_at_ms: i64,
+    pub ended_at_ms: i64,
+    pub duration_ms: Option<i64>,
+    pub ops_per_sec: Option<f64>,
+    pub p50_us: Option<i64>,
+    pub p99_us: Option<i64>,
+    pub verified: Option<bool>,
+    /// The full `RunManifest` as JSON.
+    pub manifest: String,
+}
+
+/// Which runs to read back, every set field has to match.
+#[derive(Default)]
+pub struct Filter {
+    pub backend: Option<String>,
+    /// Part of the scenario name.
+    pub scenario: Option<String>,
+    /// Start of the revision.
+    pub revision: Option<String>,
+    pub host_id: Option<String>,
+    /// Only runs started at or after this many milliseconds since the epoch.
+    pub since_ms: Option<i64>,
+    /// Newest runs only.
+    pub limit: Option<usize>,
+}
+
+impl StoredRun {
+    pub const CSV_COLUMNS: [&'static str; 12] = [
+        "run_id",
+        "backend",
+        "scenario",
+        "revision",
+        "host",
+        "started_at_ms",
+        "ended_at_ms",
+        "duration_ms",
+        "ops_per_sec",
+        "p50_us",
+        "p99_us",
+        "verified",
+    ];
+
+    /// The values of `CSV_COLUMNS`.
+    pub fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.run_id.clone(),
+            self.backend.clone(),
+            self.scenario.clone(),
+            self.revision.clone().unwrap_or_default(),
+            self.host_id.clone(),
+            self.started_at_ms.to_string(),
+            self.ended_at_ms.to_string(),
+            self.duration_ms.map(|x| x.to_string()).unwrap_or_default(),
+            self.ops_per_sec.map(|x| x.to_string()).unwrap_or_default(),
+            self.p50_us.map(|x| x.to_string()).unwrap_or_default(),
+            self.p99_us.map(|x| x.to_string()).unwrap_or_default(),
+            self.verified.map(|x| x.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+impl History {
+    /// Opens the database at `path`, creating it when it does not exist.
+    pub fn open(path: &Path) -> History {
+        let connection = Connection::open(path)
+            .unwrap_or_else(|e| panic!("Could not open history {}: {}", path.display(), e));
+        connection.execute_batch(SCHEMA).unwrap();
+        History(connection)
+    }
+
+    pub fn insert(&self, manifest: &RunManifest) {
+        self.0
+            .execute(
+                &format!(
+                    "INSERT INTO runs ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    COLUMNS
+                ),
+                params![
+                    manifest.run_id,
+                    manifest.backend,
+                    manifest.scenario,
+                    manifest.revision,
+                    manifest.host_id,
+                    manifest.started_at_ms as i64,
+                    manifest.ended_at_ms as i64,
+                    manifest.result.duration_ms().map(|x| x as i64),
+                    manifest.result.ops_per_sec(),
+                    manifest.result.latency().map(|latency| latency.p50 as i64),
+                    manifest.result.latency().map(|latency| latency.p99 as i64),
+                    manifest.verified,
+                    serde_json::to_string(manifest).unwrap(),
+                ],
+            )
+            .unwrap();
+    }
+
+    /// The runs matching `filter`, oldest first.
+    pub fn runs(&self, filter: &Filter) -> Vec<StoredRun> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(backend) = &filter.backend {
+            conditions.push("backend = ?");
+            values.push(Box::new(backend.clone()));
+        }
+        if let Some(scenario) = &filter.scenario {
+            conditions.push("scenario LIKE '%' || ? || '%'");
+            values.push(Box::new(scenario.clone()));
+        }
+        if let Some(revision) = &filter.revision {
+            conditions.push("revision LIKE ? || '%'");
+            values.push(Box::new(revision.clone()));
+        }
+        if let Some(host_id) = &filter.host_id {
+            conditions.push("host_id = ?");
+            values.push(Box::new(host_id.clone()));
+        }
+        if let Some(since_ms) = filter.since_ms {
+            conditions.push("started_at_ms >= ?");
+            values.push(Box::new(since_ms));
+        }
+
+        let mut query = format!("SELECT {} FROM runs", COLUMNS);
+        if !conditions.is_empty() {
+            query += &format!(" WHERE {}", conditions.join(" AND "));
+        }
+        query += " ORDER BY id DESC";
+        if let Some(limit) = filter.limit {
+            query += &format!(" LIMIT {}", limit);
+        }
+
+        let mut statement = self.0.prepare(&query).unwrap();
+        let mut runs = statement
+            .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                Ok(StoredRun {
+                    run_id: row.get(0)?,
+                    backend: row.get(1)?,
+                    scenario: row.get(2)?,
+                    revision: row.get(3)?,
+                    host_id: row.get(4)?,
+                    started_at_ms: row.get(5)?,
+                    ended_at_ms: row.get(6)?,
+                    duration_ms: row.get(7)?,
+                    ops_per_sec: row.get(8)?,
+                    p50_us: row.get(9)?,
+                    p99_us: row.get(10)?,
+                    verified: row.get(11)?,
+                    manifest: row.get(12)?,
+                })
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        runs.reverse();
+        runs
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sysinfo::{CpuExt, System, SystemExt};
+
+/// Version of the compiler heart was built with, set by `build.rs`.
+const RUSTC_VERSION: &str = env!("HEART_RUSTC_VERSION");
+
+/// The machine and environment a run was measured on. Fields that cannot be
+/// read on this platform are `None`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Host {
+    pub hostname: Option<String>,
+    pub os: Option<String>,
+    pub kernel: Option<String>,
+    pub cpu_model: Option<String>,
+    pub logical_cores: usize,
+    pub physical_cores: Option<usize>,
+    /// Total memory in bytes.
+    pub total_memory: u64,
+    /// The mount holding the data directory.
+    pub data_dir: Option<Mount>,
+    pub rustc: &'static str,
+}
+
+/// An entry of `/proc/self/mountinfo`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub filesystem: String,
+    pub source: String,
+    /// Options of this mount, like `rw,relatime`.
+    pub mount_options: String,
+    /// Options of the filesystem itself, like `rw,data=ordered`.
+    pub super_options: String,
+}
+
+impl Host {
+    /// Fingerprints this machine, with `data_dir` being the directory the
+    /// backends write to.
+    pub fn current(data_dir: &Path) -> Host {
+        let mut sys = System::new();
+        sys.refresh_cpu();
+        sys.refresh_memory();
+        Host {
+            hostname: sys.host_name(),
+            os: sys.long_os_version(),
+            kernel: sys.kernel_version(),
+            cpu_model: sys.cpus().first().map(|cpu| cpu.brand().to_string()),
+            logical_cores: sys.cpus().len(),
+            physical_cores: sys.physical_core_count(),
+            total_memory: sys.total_memory(),
+            data_dir: Mount::of(data_dir),
+            rustc: RUSTC_VERSION,
+        }
+    }
+
+    /// Short stable id of the fingerprint, equal for runs on the same machine
+    /// and setup.
+    pub fn id(&self) -> String {
+        // FNV-1a, the standard hasher is not stable across Rust versions.
+        let json = serde_json::to_vec(self).unwrap();
+        let hash = json.iter().fold(0xcbf29ce484222325u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{:016x}", hash)
+    }
+}
+
+impl Mount {
+    /// The mount `path` lies on, `None` where `/proc/self/mountinfo` cannot
+    /// be read.
+    pub fn of(path: &Path) -> Option<Mount> {
+        let path = path.canonicalize().ok()?;
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+        Mount::containing(&mountinfo, &path)
+    }
+
+    /// The innermost of the `mountinfo` entries holding the absolute `path`.
+    fn containing(mountinfo: &str, path: &Path) -> Option<Mount> {
+        mountinfo
+            .lines()
+            .filter_map(Mount::parse)
+            .filter(|mount| path.starts_with(&mount.mount_point))
+            // The innermost mount, later entries shadow earlier ones.
+            .fold(None, |innermost: Option<Mount>, mount| match innermost {
+                Some(innermost)
+                    if innermost.mount_point.components().count()
+                        > mount.mount_point.components().count() =>
+                {
+                    Some(innermost)
+                }
+                _ => Some(mount),
+            })
+    }
+
+    /// Parses `id parent major:minor root mount_point options [optional...] -
+    /// filesystem source super_options`.
+    fn parse(line: &str) -> Option<Mount> {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        let mount = mount.split(' ').collect::<Vec<_>>();
+        let mut filesystem = filesystem.split(' ');
+        Some(Mount {
+            mount_point: PathBuf::from(unescape(mount.get(4)?)),
+            mount_options: mount.get(5)?.to_string(),
+            filesystem: filesystem.next()?.to_string(),
+            source: unescape(filesystem.next()?),
+            super_options: filesystem.next()?.to_string(),
+        })
+    }
+}
+
+/// Undoes the octal escapes of spaces, tabs, newlines and backslashes in
+/// mountinfo paths.
+fn unescape(value: &str) -> String {
+    value
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 8:1 / / rw,relatime - ext4 /dev/sda1 rw
+36 22 8:17 / /data rw,noatime shared:1 - xfs /dev/sdb1 rw,attr2
+37 36 0:32 / /data/my\\040db rw,nosuid - tmpfs tmpfs rw,size=1024k
+38 22 0:33 / /data rw,relatime - ext4 /dev/sdc1 rw
+";
+
+    #[test]
+    fn parses_mountinfo_lines() {
+        let mount = Mount::parse(MOUNTINFO.lines().nth(2).unwrap()).unwrap();
+        assert_eq!(mount.mount_point, PathBuf::from("/data/my db"));
+        assert_eq!(mount.mount_options, "rw,nosuid");
+        assert_eq!(mount.filesystem, "tmpfs");
+        assert_eq!(mount.source, "tmpfs");
+        assert_eq!(mount.super_options, "rw,size=1024k");
+
+        assert!(Mount::parse("22 1 8:1 / / rw,relatime").is_none());
+        assert!(Mount::parse("22 1 8:1 / / rw,relatime - ext4").is_none());
+    }
+
+    #[test]
+    fn picks_the_innermost_mount() {
+        let mount = |path: &str| Mount::containing(MOUNTINFO, Path::new(path)).unwrap();
+        assert_eq!(mount("/data/my db/test").filesystem, "tmpfs");
+        // The later of two mounts on the same point shadows the earlier.
+        assert_eq!(mount("/data/test").source, "/dev/sdc1");
+        // Paths match whole components, /database is not under /data.
+        assert_eq!(mount("/database").source, "/dev/sda1");
+    }
+
+    #[test]
+    fn ids_follow_the_fingerprint() {
+        let host = Host::current(Path::new("."));
+        assert_eq!(host.id(), host.clone().id());
+        let other = Host {
+            total_memory: host.total_memory + 1,
+            ..host.clone()
+        };
+        assert_ne!(host.id(), other.id());
+    }
+}
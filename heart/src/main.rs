@@ -1,439 +1,273 @@
-use std::{time::Instant, sync::mpsc::Sender};
-use helpers::{create_percentage_test, create_transaction_size_test, CPUStatsCommand, create_simple_test, create_transaction_big_size_test};
+use std::{path::Path, thread, time::{Duration, Instant}, sync::mpsc::Sender};
+use backend::{with_backend, Backend, Durable, FileBackup, FileStore, StorageBackend, BACKUP_PATH, TABLE_NAME};
+use clap::Parser;
+use commands::Cli;
+use helpers::{CPUStatsCommand, RunOptions, TestFunction};
 
-use tenaciouszebra_dashmap::database::{
-    Database as DashMapDatabase, TableTransaction as DashMapTableTransaction,
-};
-use tenaciouszebra_file_store::database::{
-    Database as FileStoreDatabase, TableTransaction as FileStoreTableTransaction,
-};
-use tenaciouszebra_rocksdb_wal::database::{Database, TableTransaction};
+use tenaciouszebra_file_store::database::Database as FileStoreDatabase;
 
-use tenaciouszebra_okaywal::database::{Database as OkayWalDatabase, TableTransaction as OkayWalTableTransaction};
-
-use tenaciouszebra_single_rocksdb::database::{Database as SingleRocksdbDatabase, TableTransaction as SingleRocksdbTableTransaction};
-
-use tenaciouszebra_pickledb::database::{Database as PickleDbDatabase, TableTransaction as PickleDbTableTransaction};
-
-use crate::helpers::with_percentage_true;
+use crate::arrival::{wait_until, Arrival};
+use crate::metrics::{BackupPhases, DiskFootprint, IoCounters, Latencies, RunResult};
+use crate::workload::{Key, Value, Workload, WorkloadConfig};
 
+mod arrival;
+mod backend;
+mod cadence;
 mod commands;
+mod crash;
+mod distribution;
 mod helpers;
-
-fn get_test_method(args: &Vec<String>) -> String {
-    for i in 0..args.len() {
-        if args[i] == format!("--{}", "method") {
-            return args[i + 1].clone();
-        }
-    }
-    String::from("none")
-}
-
-fn get_backup_type(args: &Vec<String>) -> String {
-    for i in 0..args.len() {
-        if args[i] == format!("--{}", "backup-type") {
-            return args[i + 1].clone();
-        }
-    }
-    String::from("none")
-}
-
-
-fn run_rocksdb_wal_test(
-    write_percentage: i32,
-    transaction_size: usize,
-    transaction_count: usize,
+mod history;
+mod host;
+mod manifest;
+mod metrics;
+mod recovery;
+mod scenario;
+mod soak;
+mod stats;
+mod verify;
+mod workload;
+mod ycsb;
+
+fn run_test<B: StorageBackend>(
+    config: &WorkloadConfig,
+    options: &RunOptions,
     tx: &Sender<CPUStatsCommand>,
-) -> u128 {
-    let path = "test";
-    let db = Database::<String, usize>::new(path);
-    let test_table = db.empty_table("test");
-
-    let mut first_transaction = TableTransaction::new();
-    for i in 0..transaction_size {
-        first_transaction.set(format!("first {}", i), i).unwrap();
-    }
-    test_table.execute(first_transaction);
-
-    let mut transactions = Vec::<TableTransaction<String, usize>>::new();
-
-    let mut get_count = 0;
-
-    for i in 0..transaction_count {
-        let mut modify = TableTransaction::new();
-        for j in 0..transaction_size {
-            if with_percentage_true(write_percentage) {
-                modify.set(format!("{}{}", i, j), j).unwrap();
-            } else {
-                modify.get(format!("first {}", get_count)).unwrap();
-                get_count += 1;
+) -> RunResult {
+    let workload = Workload::generate(config);
+    let bytes_set = workload.bytes_set();
+    let read_modify_writes = workload.read_modify_writes;
+    // Kept to check the run against.
+    let expected = options
+        .verify
+        .then(|| (workload.preload.clone(), workload.transactions.clone()));
+
+    let backend = B::open();
+    backend.execute(B::build_transaction(workload.preload));
+
+    // Closed loops give every worker a contiguous slice of the transactions,
+    // open loops deal them out in turn so every worker keeps up with its
+    // share of the schedule.
+    let schedule = config
+        .arrival
+        .schedule(config.seed, config.transaction_size, config.transaction_count);
+    let mut slices = (0..config.threads).map(|_| Vec::new()).collect::<Vec<_>>();
+    let transactions = workload
+        .transactions
+        .into_iter()
+        .map(B::build_transaction_with_queries);
+    match &schedule {
+        Some(schedule) => {
+            for (i, (transaction, &offset)) in transactions.zip(schedule).enumerate() {
+                slices[i % config.threads].push((i, Some(offset), transaction));
             }
         }
-        transactions.push(modify);
-    }
-
-    tx.send(CPUStatsCommand::Start).unwrap();
-    let start: Instant = Instant::now();
-
-    for transaction in transactions {
-        test_table.execute(transaction);
-    }
-
-    let duration: std::time::Duration = start.elapsed();
-    tx.send(CPUStatsCommand::Stop).unwrap();
-
-    std::fs::remove_dir_all(path).unwrap();
-
-    println!(
-        "Time elapsed  {:?}, write percentage: {}, transaction_size {}, transaction_count {}",
-        duration, write_percentage, transaction_size, transaction_count
-    );
-    duration.as_millis()
-}
-
-fn run_no_backup_test(
-    write_percentage: i32,
-    transaction_size: usize,
-    transaction_count: usize,
-    tx: &Sender<CPUStatsCommand>,
-) -> u128 {
-    let db = FileStoreDatabase::<String, usize>::new();
-    let test_table: std::sync::Arc<tenaciouszebra_file_store::database::Table<String, usize>> = db.empty_table("test");
-
-    let mut first_transaction = FileStoreTableTransaction::new();
-    for i in 0..transaction_size {
-        first_transaction.set(format!("first {}", i), i).unwrap();
-    }
-    test_table.execute(first_transaction);
-
-    let mut transactions = Vec::<FileStoreTableTransaction<String, usize>>::new();
-    let mut get_count = 0;
-    for i in 0..transaction_count {
-        let mut modify = FileStoreTableTransaction::new();
-        modify.set(String::from("first"), i).unwrap();
-        for j in 0..transaction_size {
-            if with_percentage_true(write_percentage) {
-                modify.set(format!("{}{}", i, j), j).unwrap();
-            } else {
-                modify.get(&format!("first {}", get_count)).unwrap();
-                get_count += 1;
+        None => {
+            let slice_len = config.transaction_count.div_ceil(config.threads).max(1);
+            for (i, transaction) in transactions.enumerate() {
+                slices[i / slice_len].push((i, None, transaction));
             }
         }
-        transactions.push(modify);
     }
 
+    let io_start = IoCounters::read();
     tx.send(CPUStatsCommand::Start).unwrap();
     let start: Instant = Instant::now();
 
-    for transaction in transactions {
-        test_table.execute(transaction);
-    }
+    let (thread_latencies, responses): (Vec<_>, Vec<_>) = thread::scope(|scope| {
+        let workers = slices
+            .into_iter()
+            .map(|slice| {
+                let backend = &backend;
+                scope.spawn(move || {
+                    let mut latencies = Latencies::new();
+                    let mut responses = Vec::new();
+                    for (index, offset, (transaction, queries)) in slice {
+                        // Open-loop latency counts from the intended start,
+                        // including the wait for earlier transactions.
+                        let transaction_start = match offset {
+                            Some(offset) => {
+                                let intended = start + offset;
+                                wait_until(intended);
+                                intended
+                            }
+                            None => Instant::now(),
+                        };
+                        let response = backend.execute(transaction);
+                        latencies.record(transaction_start.elapsed());
+                        if options.verify {
+                            responses.push((index, response, queries));
+                        }
+                    }
+                    (latencies, responses)
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .unzip()
+    });
 
     let duration = start.elapsed();
     tx.send(CPUStatsCommand::Stop).unwrap();
+    let io = IoCounters::since(io_start);
+
+    let footprint = B::DATA_DIR.map(|dir| DiskFootprint::measure(Path::new(dir)));
+    let verification = expected.map(|(preload, transactions)| {
+        let executed = responses
+            .iter()
+            .map(|responses| verify::executed(responses))
+            .collect::<Vec<_>>();
+        verify::verify(&backend, &preload, &transactions, &executed)
+    });
+    backend.teardown();
 
     println!(
-        "Time elapsed  {:?}, write percentage: {}, transaction_size {}, transaction_count {}",
-        duration, write_percentage, transaction_size, transaction_count
+        "Time elapsed  {:?}, backend {}, {}",
+        duration,
+        B::NAME,
+        config
     );
-    duration.as_millis()
-}
-
-
-fn run_okaywal_test(
-    write_percentage: i32,
-    transaction_size: usize,
-    transaction_count: usize,
-    tx: &Sender<CPUStatsCommand>,
-) -> u128 {
-    let path = "test";
-    let db = OkayWalDatabase::<String, usize>::new(path);
-    let test_table = db.empty_table("test");
-
-    let mut first_transaction = OkayWalTableTransaction::new();
-    for i in 0..transaction_size {
-        first_transaction.set(format!("first {}", i), i).unwrap();
+    let mut latencies = Latencies::new();
+    for worker in &thread_latencies {
+        latencies.merge(worker);
     }
-    test_table.execute(first_transaction);
-
-    let mut transactions = Vec::<OkayWalTableTransaction<String, usize>>::new();
-
-    let mut get_count = 0;
-
-    for i in 0..transaction_count {
-        let mut modify = OkayWalTableTransaction::new();
-        for j in 0..transaction_size {
-            if with_percentage_true(write_percentage) {
-                modify.set(format!("{}{}", i, j), j).unwrap();
-            } else {
-                modify.get(&format!("first {}", get_count)).unwrap();
-                get_count += 1;
-            }
-        }
-        transactions.push(modify);
-    }
-
-    tx.send(CPUStatsCommand::Start).unwrap();
-    let start: Instant = Instant::now();
-
-    for transaction in transactions {
-        test_table.execute(transaction);
+    RunResult {
+        duration: duration.as_millis(),
+        duration_secs: duration.as_secs_f64(),
+        throughput: throughput(config, duration),
+        latency: latencies.summary(),
+        thread_latencies: thread_latencies.iter().map(Latencies::summary).collect(),
+        bytes_set,
+        read_modify_writes,
+        io,
+        footprint,
+        phases: None,
+        verification,
     }
-
-    let duration = start.elapsed();
-    tx.send(CPUStatsCommand::Stop).unwrap();
-
-    std::fs::remove_dir_all(path).unwrap();
-
-    println!(
-        "Time elapsed  {:?}, write percentage: {}, transaction_size {}, transaction_count {}",
-        duration, write_percentage, transaction_size, transaction_count
-    );
-    duration.as_millis()
 }
 
-fn run_no_backup_dashmap_test(
-    write_percentage: i32,
-    transaction_size: usize,
-    transaction_count: usize,
-    tx: &Sender<CPUStatsCommand>,
-) -> u128 {
-    let db = DashMapDatabase::<String, usize>::new();
-    let mut test_table = db.empty_table();
-
-    let mut first_transaction = DashMapTableTransaction::new();
-    for i in 0..transaction_size {
-        first_transaction.set(format!("first {}", i), i).unwrap();
-    }
-    test_table.execute(first_transaction);
-
-    let mut transactions = Vec::<DashMapTableTransaction<String, usize>>::new();
-    let mut get_count = 0;
-    for i in 0..transaction_count {
-        let mut modify = DashMapTableTransaction::new();
-        for j in 0..transaction_size {
-            if with_percentage_true(write_percentage) {
-                modify.set(format!("{}{}", i, j), j).unwrap();
-            } else {
-                modify.get(&format!("first {}", get_count)).unwrap();
-                get_count += 1;
-            }
-        }
-        transactions.push(modify);
-    }
-
-    tx.send(CPUStatsCommand::Start).unwrap();
-    let start: Instant = Instant::now();
-
-    for transaction in transactions {
-        test_table.execute(transaction);
-    }
-
-    let duration = start.elapsed();
-    tx.send(CPUStatsCommand::Stop).unwrap();
-
-    println!(
-        "Time elapsed  {:?}, write percentage: {}, transaction_size {}, transaction_count {}",
-        duration, write_percentage, transaction_size, transaction_count
-    );
-    duration.as_millis()
+/// Operations per second of a run of `config` that took `duration`.
+fn throughput(config: &WorkloadConfig, duration: Duration) -> f64 {
+    (config.transaction_size * config.transaction_count) as f64 / duration.as_secs_f64()
 }
 
+/// Restores the database from its backup, executes one transaction and backs
+/// it up again for every transaction. The transactions are built before
+/// measuring and the three phases are timed separately.
 fn run_file_backup_test(
-    write_percentage: i32,
-    transaction_size: usize,
-    transaction_count: usize,
+    config: &WorkloadConfig,
+    options: &RunOptions,
     tx: &Sender<CPUStatsCommand>,
-) -> u128 {
-    let mut db = FileStoreDatabase::<String, usize>::new();
-    db.empty_table("test");
-    db.backup("./backup");
-    let test_table: std::sync::Arc<tenaciouszebra_file_store::database::Table<String, usize>> = db.get_table("test").unwrap();
-
-    let mut first_transaction = FileStoreTableTransaction::new();
-    for i in 0..transaction_size {
-        first_transaction.set(format!("first {}", i), i).unwrap();
-    }
-    test_table.execute(first_transaction);
-
-    let mut get_counter = 0;
-
+) -> RunResult {
+    assert_eq!(config.threads, 1, "The file backup test runs single threaded");
+    assert_eq!(config.arrival, Arrival::Closed, "The file backup test runs closed loop");
+    let workload = Workload::generate(config);
+    let bytes_set = workload.bytes_set();
+    let read_modify_writes = workload.read_modify_writes;
+    // Kept to check the run against.
+    let expected = options
+        .verify
+        .then(|| (workload.preload.clone(), workload.transactions.clone()));
+
+    let mut db = FileStoreDatabase::<Key, Value>::new();
+    let table = db.empty_table(TABLE_NAME);
+    table.execute(FileStore::build_transaction(workload.preload));
+    db.backup(BACKUP_PATH);
+
+    let transactions = workload
+        .transactions
+        .into_iter()
+        .map(FileStore::build_transaction_with_queries)
+        .collect::<Vec<_>>();
+    let mut responses = Vec::new();
+
+    let mut latencies = Latencies::new();
+    let mut restore = Latencies::new();
+    let mut execute = Latencies::new();
+    let mut backup = Latencies::new();
+    let (mut restore_total, mut execute_total, mut backup_total) =
+        (Duration::ZERO, Duration::ZERO, Duration::ZERO);
+
+    let io_start = IoCounters::read();
     tx.send(CPUStatsCommand::Start).unwrap();
     let start: Instant = Instant::now();
 
-    for i in 0..transaction_count {
-        let mut modify = FileStoreTableTransaction::new();
-        modify.set(String::from("first"), i).unwrap();
-        for j in 0..transaction_size {
-            if with_percentage_true(write_percentage) {
-                modify.set(format!("{}{}", i, j), j).unwrap();
-            } else {
-                modify.get(&format!("first {}", get_counter)).unwrap();
-                get_counter += 1;
-            }
+    for (index, (transaction, queries)) in transactions.into_iter().enumerate() {
+        let restore_start = Instant::now();
+        db = FileStoreDatabase::restore(BACKUP_PATH);
+        let table = db.get_table(TABLE_NAME).unwrap();
+
+        let execute_start = Instant::now();
+        let response = table.execute(transaction);
+
+        let backup_start = Instant::now();
+        db.backup(BACKUP_PATH);
+        let end = Instant::now();
+
+        restore.record(execute_start - restore_start);
+        execute.record(backup_start - execute_start);
+        backup.record(end - backup_start);
+        restore_total += execute_start - restore_start;
+        execute_total += backup_start - execute_start;
+        backup_total += end - backup_start;
+        latencies.record(end - restore_start);
+        if options.verify {
+            responses.push((index, response, queries));
         }
-        db = FileStoreDatabase::restore("./backup");
-
-        test_table.execute(modify);
-        db.backup("./backup");
     }
 
     let duration = start.elapsed();
     tx.send(CPUStatsCommand::Stop).unwrap();
-
-    std::fs::remove_dir_all("./backup").unwrap();
+    let io = IoCounters::since(io_start);
+
+    let footprint = Some(DiskFootprint::measure(Path::new(BACKUP_PATH)));
+    // The backup the run left is what a restarted node would see.
+    let verification = expected.map(|(preload, transactions)| {
+        let executed = verify::executed(&responses);
+        verify::verify(&FileBackup::reopen(), &preload, &transactions, &[executed])
+    });
+    std::fs::remove_dir_all(BACKUP_PATH).unwrap();
     println!(
-        "Time elapsed  {:?}, write percentage: {}, transaction_size {}, transaction_count {}",
-        duration, write_percentage, transaction_size, transaction_count
+        "Time elapsed  {:?} (restore {:?}, execute {:?}, backup {:?}), backend {}, {}",
+        duration,
+        restore_total,
+        execute_total,
+        backup_total,
+        Backend::FileBackup.name(),
+        config
     );
-    duration.as_millis()
-}
-
-fn run_single_rocksdb_backup_test(
-    write_percentage: i32,
-    transaction_size: usize,
-    transaction_count: usize,
-    tx: &Sender<CPUStatsCommand>,
-) -> u128 {
-    let path = "test";
-    let db = SingleRocksdbDatabase::<String, usize>::new(path);
-    let test_table = db.empty_table("test");
-
-    let mut first_transaction = SingleRocksdbTableTransaction::new();
-    for i in 0..transaction_size {
-        first_transaction.set(format!("first {}", i), i).unwrap();
-    }
-    test_table.execute(first_transaction);
-
-    let mut transactions: Vec<SingleRocksdbTableTransaction<String, usize>> = Vec::<SingleRocksdbTableTransaction<String, usize>>::new();
-
-    let mut get_count = 0;
-
-    for i in 0..transaction_count {
-        let mut modify = SingleRocksdbTableTransaction::new();
-        for j in 0..transaction_size {
-            if with_percentage_true(write_percentage) {
-                modify.set(format!("{}{}", i, j), j).unwrap();
-            } else {
-                modify.get(&format!("first {}", get_count)).unwrap();
-                get_count += 1;
-            }
-        }
-        transactions.push(modify);
+    RunResult {
+        duration: duration.as_millis(),
+        duration_secs: duration.as_secs_f64(),
+        throughput: throughput(config, duration),
+        latency: latencies.summary(),
+        thread_latencies: vec![latencies.summary()],
+        bytes_set,
+        read_modify_writes,
+        io,
+        footprint,
+        phases: Some(BackupPhases {
+            restore_ms: restore_total.as_millis(),
+            execute_ms: execute_total.as_millis(),
+            backup_ms: backup_total.as_millis(),
+            restore: restore.summary(),
+            execute: execute.summary(),
+            backup: backup.summary(),
+        }),
+        verification,
     }
-
-    tx.send(CPUStatsCommand::Start).unwrap();
-    let start: Instant = Instant::now();
-
-    for transaction in transactions {
-        test_table.execute(transaction);
-    }
-
-    let duration = start.elapsed();
-    tx.send(CPUStatsCommand::Stop).unwrap();
-
-    std::fs::remove_dir_all(path).unwrap();
-
-    println!(
-        "Time elapsed  {:?}, write percentage: {}, transaction_size {}, transaction_count {}",
-        duration, write_percentage, transaction_size, transaction_count
-    );
-    duration.as_millis()
 }
 
-fn run_pickledb_backup_test(
-    write_percentage: i32,
-    transaction_size: usize,
-    transaction_count: usize,
-    tx: &Sender<CPUStatsCommand>,
-) -> u128 {
-    let path = "test";
-    let db = PickleDbDatabase::<String, usize>::new(path);
-    let test_table = db.empty_table("test");
-
-    let mut first_transaction: PickleDbTableTransaction<String, usize> = PickleDbTableTransaction::new();
-    for i in 0..transaction_size {
-        first_transaction.set(format!("first {}", i), i).unwrap();
-    }
-    test_table.execute(first_transaction);
-
-    let mut transactions: Vec<PickleDbTableTransaction<String, usize>> = Vec::<PickleDbTableTransaction<String, usize>>::new();
-
-    let mut get_count = 0;
-
-    for i in 0..transaction_count {
-        let mut modify = PickleDbTableTransaction::new();
-        for j in 0..transaction_size {
-            if with_percentage_true(write_percentage) {
-                modify.set(format!("{}{}", i, j), j).unwrap();
-            } else {
-                modify.get(&format!("first {}", get_count)).unwrap();
-                get_count += 1;
-            }
-        }
-        transactions.push(modify);
-    }
-
-    tx.send(CPUStatsCommand::Start).unwrap();
-    let start: Instant = Instant::now();
-
-    for transaction in transactions {
-        test_table.execute(transaction);
+/// Returns the benchmark function running against `backend`.
+fn test_function(backend: Backend) -> TestFunction {
+    match backend {
+        Backend::FileBackup => run_file_backup_test,
+        _ => with_backend!(backend, B => run_test::<B> as TestFunction),
     }
-
-    let duration = start.elapsed();
-    tx.send(CPUStatsCommand::Stop).unwrap();
-
-    std::fs::remove_dir_all(path).unwrap();
-
-    println!(
-        "Time elapsed  {:?}, write percentage: {}, transaction_size {}, transaction_count {}",
-        duration, write_percentage, transaction_size, transaction_count
-    );
-    duration.as_millis()
 }
 
 fn main() {
-    // let args: Vec<String> = env::args().collect();
-
-    // let test_method = get_test_method(&args);
-    // let backup_type = get_backup_type(&args);
-
-    std::fs::create_dir_all("results").unwrap();
-
-    // create_simple_test(run_rocksdb_wal_test, "simple_rocksdb_wal");
-    // create_simple_test(run_no_backup_test, "simple_no_backup");
-    // create_simple_test(run_file_backup_test, "simple_file_backup");
-    // create_simple_test(run_no_backup_dashmap_test, "simple_no_backup_dashmap");
-    // create_simple_test(run_okaywal_test, "simple_okaywal");
-    // create_simple_test(run_single_rocksdb_backup_test, "simple_single_rocksdb");
-    // create_simple_test(run_pickledb_backup_test, "simple_pickledb");
-
-
-    // create_percentage_test(run_rocksdb_wal_test, "write_percentage_rocksdb_wal");
-    // create_percentage_test(run_no_backup_test, "write_percentage_no_backup");
-    // create_percentage_test(run_file_backup_test, "write_percentage_with_file_backup");
-    // create_percentage_test(
-    //     run_no_backup_dashmap_test,
-    //     "write_percentage_no_backup_dashmap",
-    // );
-    // create_percentage_test(run_okaywal_test, "write_percentage_okaywal");
-    // create_percentage_test(run_pickledb_backup_test, "write_percentage_pickledb");
-
-
-    // create_transaction_size_test(run_rocksdb_wal_test, "transaction_size_rocksdb_wal");
-    // create_transaction_size_test(run_no_backup_test, "transaction_size_no_backup");
-    // create_transaction_size_test(run_file_backup_test, "transaction_size_with_file_backup");
-    // create_transaction_size_test(
-    //     run_no_backup_dashmap_test,
-    //     "transaction_size_no_backup_dashmap",
-    // );
-    // create_transaction_size_test(run_okaywal_test, "transaction_size_okaywal");
-    // create_transaction_size_test(run_pickledb_backup_test, "transaction_size_pickledb");
-
-    create_transaction_big_size_test(run_no_backup_test, "transaction_big_size_no_backup");
-    create_transaction_big_size_test(run_rocksdb_wal_test, "transaction_big_size_rocksdb_wal");
-    create_transaction_big_size_test(run_okaywal_test, "transaction_big_size_okaywal");
-    create_percentage_test(run_pickledb_backup_test, "transaction_big_size_pickledb");
-
+    commands::run(Cli::parse());
 }
@@ -0,0 +1,180 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::cadence::{BackupPolicy, CadenceRun};
+use crate::crash::Trial;
+use crate::history::History;
+use crate::host::Host;
+use crate::metrics::{LatencySummary, RunResult};
+use crate::recovery::Recovery;
+use crate::soak::Window;
+use crate::workload::WorkloadConfig;
+
+/// The lock file heart was built with, it pins the commit of every branch.
+const CARGO_LOCK: &str = include_str!("../Cargo.lock");
+
+#[derive(Deserialize)]
+struct Lock {
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    source: Option<String>,
+}
+
+/// The commit of `backend`'s tenaciouszebra branch heart was built against,
+/// `None` when the lock file does not pin it.
+pub fn revision(backend: Backend) -> Option<String> {
+    let lock: Lock = toml::from_str(CARGO_LOCK).ok()?;
+    lock.package
+        .into_iter()
+        .filter(|package| package.name == "tenaciouszebra")
+        .filter_map(|package| package.source)
+        .find_map(|source| {
+            // git+<url>?branch=<branch>#<commit>
+            let (url, commit) = source.split_once('#')?;
+            let branch = url.split_once("branch=")?.1.replace("%2F", "/");
+            (branch == backend.branch()).then(|| commit.to_string())
+        })
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn unix_ms(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_millis()
+}
+
+/// Everything known about one measured run, written as one line of JSON.
+/// The result CSVs are derived from the same runs.
+#[derive(Serialize)]
+pub struct RunManifest<'a> {
+    pub run_id: &'a str,
+    pub backend: &'static str,
+    /// Commit of the backend's tenaciouszebra branch.
+    pub revision: Option<&'a str>,
+    /// Name of the sweep or scenario the run belongs to.
+    pub scenario: &'a str,
+    pub repetition: usize,
+    pub seed: u64,
+    pub workload: &'a WorkloadConfig,
+    /// `Host::id` of `host`, also written to the result CSV.
+    pub host_id: &'a str,
+    pub host: &'a Host,
+    pub started_at_ms: u128,
+    pub ended_at_ms: u128,
+    pub result: Measurement<'a>,
+    /// Whether the run passed verification, `None` when it did not run.
+    pub verified: Option<bool>,
+    pub divergence: Option<&'a str>,
+}
+
+/// What a run measured, depending on the test it belongs to.
+#[derive(Serialize)]
+#[serde(tag = "test", rename_all = "snake_case")]
+pub enum Measurement<'a> {
+    Benchmark(&'a RunResult),
+    /// One kill and reopen, verified when the reopened table was as expected.
+    Crash(&'a Trial),
+    Recovery(&'a Recovery),
+    Cadence {
+        policy: BackupPolicy,
+        run: &'a CadenceRun,
+    },
+    /// One window of a soak run, its repetition is the window index.
+    SoakWindow(&'a Window),
+}
+
+impl Measurement<'_> {
+    /// Duration of the run in milliseconds, the time to the first read for
+    /// restarts.
+    pub fn duration_ms(&self) -> Option<u128> {
+        match self {
+            Measurement::Benchmark(result) => Some(result.duration),
+            Measurement::Recovery(recovery) => recovery.first_read_ms,
+            Measurement::Cadence { run, .. } => Some(run.duration.as_millis()),
+            Measurement::Crash(_) | Measurement::SoakWindow(_) => None,
+        }
+    }
+
+    pub fn ops_per_sec(&self) -> Option<f64> {
+        match self {
+            Measurement::Benchmark(result) => Some(result.throughput),
+            Measurement::Cadence { run, .. } => Some(run.throughput),
+            Measurement::SoakWindow(window) => Some(window.throughput),
+            Measurement::Crash(_) | Measurement::Recovery(_) => None,
+        }
+    }
+
+    pub fn latency(&self) -> Option<&LatencySummary> {
+        match self {
+            Measurement::Benchmark(result) => Some(&result.latency),
+            Measurement::SoakWindow(window) => Some(&window.latency),
+            _ => None,
+        }
+    }
+}
+
+/// Appends `RunManifest`s to `{file_name}.jsonl`, one per line.
+pub struct ManifestWriter(BufWriter<File>);
+
+impl ManifestWriter {
+    pub fn create(output_dir: &Path, file_name: &str) -> ManifestWriter {
+        let path = output_dir.join(format!("{}.jsonl", file_name));
+        ManifestWriter(BufWriter::new(File::create(path).unwrap()))
+    }
+
+    /// Writes `manifest` and flushes, so the runs of an aborted sweep are kept.
+    pub fn write(&mut self, manifest: &RunManifest) {
+        serde_json::to_writer(&mut self.0, manifest).unwrap();
+        self.0.write_all(b"\n").unwrap();
+        self.0.flush().unwrap();
+    }
+}
+
+/// Keeps the runs of one sweep: hands out their ids and writes their
+/// manifests to `{file_name}.jsonl` and the history.
+pub struct Recorder {
+    /// The machine the sweep runs on.
+    pub host: Host,
+    /// `Host::id` of `host`, also written to the result CSVs.
+    pub host_id: String,
+    file_name: String,
+    /// Start of the sweep in milliseconds since the epoch, keeps the runs of
+    /// repeated sweeps apart.
+    invocation: u128,
+    manifests: RefCell<ManifestWriter>,
+    history: History,
+}
+
+impl Recorder {
+    pub fn create(output_dir: &Path, file_name: &str, history: &Path) -> Recorder {
+        // The backends keep their data under the working directory.
+        let host = Host::current(Path::new("."));
+        Recorder {
+            host_id: host.id(),
+            host,
+            file_name: file_name.to_string(),
+            invocation: unix_ms(SystemTime::now()),
+            manifests: RefCell::new(ManifestWriter::create(output_dir, file_name)),
+            history: History::open(history),
+        }
+    }
+
+    /// Identifies a measured run in the result CSVs, the samples taken
+    /// during it, its manifest and the history.
+    pub fn run_id(&self, point: usize, repetition: usize) -> String {
+        format!("{}-{}-{}-{}", self.file_name, self.invocation, point, repetition)
+    }
+
+    pub fn record(&self, manifest: &RunManifest) {
+        self.manifests.borrow_mut().write(manifest);
+        self.history.insert(manifest);
+    }
+}
@@ -0,0 +1,438 @@
+use std::path::Path;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+
+/// Latencies of individual `execute` calls, in microseconds.
+pub struct Latencies(Histogram<u64>);
+
+impl Latencies {
+    pub fn new() -> Self {
+        Latencies(Histogram::new(3).unwrap())
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        // Grows the histogram, it starts out tracking values up to 2 µs.
+        self.0.record(latency.as_micros() as u64).unwrap();
+    }
+
+    /// Adds the latencies recorded by another worker.
+    pub fn merge(&mut self, other: &Latencies) {
+        self.0.add(&other.0).unwrap();
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            p50: self.0.value_at_quantile(0.5),
+            p90: self.0.value_at_quantile(0.9),
+            p99: self.0.value_at_quantile(0.99),
+            p999: self.0.value_at_quantile(0.999),
+            max: self.0.max(),
+        }
+    }
+}
+
+/// Percentiles of the transaction latency, in microseconds.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct LatencySummary {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+/// The storage counters of `/proc/self/io`, summed over all threads of this
+/// process, including the CPU sampler writing its CSVs.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct IoCounters {
+    /// Bytes fetched from the storage layer.
+    pub read_bytes: u64,
+    /// Bytes sent to the storage layer, counted when the page cache is dirtied.
+    pub write_bytes: u64,
+    pub read_syscalls: u64,
+    pub write_syscalls: u64,
+}
+
+impl IoCounters {
+    pub const CSV_COLUMNS: [&'static str; 4] =
+        ["read_bytes", "write_bytes", "read_syscalls", "write_syscalls"];
+
+    /// Current counters, `None` where `/proc/self/io` is not available, e.g.
+    /// outside Linux or on kernels without task I/O accounting.
+    pub fn read() -> Option<Self> {
+        Self::parse(&std::fs::read_to_string("/proc/self/io").ok()?)
+    }
+
+    /// Parses the `name: value` lines of `/proc/[pid]/io`.
+    fn parse(content: &str) -> Option<Self> {
+        let mut counters = IoCounters::default();
+        for line in content.lines() {
+            let (name, value) = line.split_once(':')?;
+            let value = value.trim().parse().ok()?;
+            match name {
+                "read_bytes" => counters.read_bytes = value,
+                "write_bytes" => counters.write_bytes = value,
+                "syscr" => counters.read_syscalls = value,
+                "syscw" => counters.write_syscalls = value,
+                _ => (),
+            }
+        }
+        Some(counters)
+    }
+
+    /// What happened since `start` was read, `None` if either read failed.
+    pub fn since(start: Option<IoCounters>) -> Option<Self> {
+        let (start, end) = start.zip(Self::read())?;
+        Some(IoCounters {
+            read_bytes: end.read_bytes.saturating_sub(start.read_bytes),
+            write_bytes: end.write_bytes.saturating_sub(start.write_bytes),
+            read_syscalls: end.read_syscalls.saturating_sub(start.read_syscalls),
+            write_syscalls: end.write_syscalls.saturating_sub(start.write_syscalls),
+        })
+    }
+
+    pub fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.read_bytes.to_string(),
+            self.write_bytes.to_string(),
+            self.read_syscalls.to_string(),
+            self.write_syscalls.to_string(),
+        ]
+    }
+}
+
+/// Clock ticks per second of the times in `/proc/[pid]/stat`, which the
+/// kernel reports in `USER_HZ`, 100 on every Linux architecture we run on.
+const USER_HZ: u64 = 100;
+
+/// CPU time and thread count of this process, from `/proc/self/stat`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcessCpu {
+    pub user_ms: u64,
+    pub system_ms: u64,
+    pub threads: u64,
+}
+
+impl ProcessCpu {
+    pub const CSV_COLUMNS: [&'static str; 3] = ["process_user_ms", "process_system_ms", "process_threads"];
+
+    /// Current values, `None` where `/proc/self/stat` is not available.
+    pub fn read() -> Option<Self> {
+        Self::parse(&std::fs::read_to_string("/proc/self/stat").ok()?)
+    }
+
+    /// Parses the single line of `/proc/[pid]/stat`.
+    fn parse(content: &str) -> Option<Self> {
+        // The command name in parentheses may contain spaces, the fields
+        // after it start with the state, field 3 in proc(5).
+        let (_, fields) = content.rsplit_once(')')?;
+        let fields = fields.split_whitespace().collect::<Vec<_>>();
+        let field = |number: usize| fields.get(number - 3)?.parse::<u64>().ok();
+        Some(ProcessCpu {
+            user_ms: field(14)? * 1000 / USER_HZ,
+            system_ms: field(15)? * 1000 / USER_HZ,
+            threads: field(20)?,
+        })
+    }
+
+    pub fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.user_ms.to_string(),
+            self.system_ms.to_string(),
+            self.threads.to_string(),
+        ]
+    }
+}
+
+/// Size of a backend's data directory, with the bytes split by file type.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct DiskFootprint {
+    pub total_bytes: u64,
+    pub file_count: u64,
+    /// Write-ahead log segments, rocksdb `*.log` and okaywal `wal-*` files.
+    pub wal_bytes: u64,
+    /// Rocksdb sorted string tables.
+    pub sst_bytes: u64,
+    /// Backups, snapshots and checkpoints.
+    pub snapshot_bytes: u64,
+    /// Everything else, e.g. manifests, options and pickledb files.
+    pub other_bytes: u64,
+}
+
+impl DiskFootprint {
+    pub const CSV_COLUMNS: [&'static str; 6] = [
+        "disk_bytes",
+        "disk_files",
+        "wal_bytes",
+        "sst_bytes",
+        "snapshot_bytes",
+        "other_disk_bytes",
+    ];
+
+    /// Walks `dir` recursively and adds up the size of every file in it.
+    pub fn measure(dir: &Path) -> Self {
+        let mut footprint = DiskFootprint::default();
+        footprint.add_dir(dir, dir);
+        footprint
+    }
+
+    fn add_dir(&mut self, root: &Path, dir: &Path) {
+        let entries = std::fs::read_dir(dir)
+            .unwrap_or_else(|e| panic!("Could not read data directory {}: {}", dir.display(), e));
+        for entry in entries {
+            let entry = entry.unwrap();
+            let metadata = entry.metadata().unwrap();
+            let path = entry.path();
+            if metadata.is_dir() {
+                self.add_dir(root, &path);
+                continue;
+            }
+
+            let size = metadata.len();
+            self.total_bytes += size;
+            self.file_count += 1;
+
+            let relative = path.strip_prefix(root).unwrap().to_string_lossy().to_lowercase();
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            if ["snapshot", "checkpoint", "backup"].iter().any(|x| relative.contains(x)) {
+                self.snapshot_bytes += size;
+            } else if name.ends_with(".sst") {
+                self.sst_bytes += size;
+            } else if name.ends_with(".log") || name.starts_with("wal") {
+                self.wal_bytes += size;
+            } else {
+                self.other_bytes += size;
+            }
+        }
+    }
+
+    pub fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.total_bytes.to_string(),
+            self.file_count.to_string(),
+            self.wal_bytes.to_string(),
+            self.sst_bytes.to_string(),
+            self.snapshot_bytes.to_string(),
+            self.other_bytes.to_string(),
+        ]
+    }
+}
+
+/// Where the file backup test spends each transaction: restoring the
+/// database from its backup, executing against it and backing it up again.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct BackupPhases {
+    /// Total time spent in each phase, in milliseconds.
+    pub restore_ms: u128,
+    pub execute_ms: u128,
+    pub backup_ms: u128,
+    pub restore: LatencySummary,
+    pub execute: LatencySummary,
+    pub backup: LatencySummary,
+}
+
+impl BackupPhases {
+    pub const CSV_COLUMNS: [&'static str; 9] = [
+        "restore_ms",
+        "execute_ms",
+        "backup_ms",
+        "restore_p50_us",
+        "restore_p99_us",
+        "execute_p50_us",
+        "execute_p99_us",
+        "backup_p50_us",
+        "backup_p99_us",
+    ];
+
+    /// The values of `CSV_COLUMNS`.
+    pub fn csv_record(&self) -> Vec<String> {
+        [
+            self.restore_ms as u64,
+            self.execute_ms as u64,
+            self.backup_ms as u64,
+            self.restore.p50,
+            self.restore.p99,
+            self.execute.p50,
+            self.execute.p99,
+            self.backup.p50,
+            self.backup.p99,
+        ]
+        .map(|x| x.to_string())
+        .to_vec()
+    }
+}
+
+/// What a single benchmark run measured.
+#[derive(Clone, Debug, Serialize)]
+pub struct RunResult {
+    /// Total time spent executing the transactions, in milliseconds.
+    pub duration: u128,
+    /// The same time in seconds, without truncating to milliseconds.
+    pub duration_secs: f64,
+    /// Operations executed per second, over all threads.
+    pub throughput: f64,
+    /// Latency over the transactions of all threads.
+    pub latency: LatencySummary,
+    /// Latency of each worker thread's transactions.
+    pub thread_latencies: Vec<LatencySummary>,
+    /// Key and value bytes of all sets in the measured transactions.
+    pub bytes_set: u64,
+    /// Read-modify-writes of the measured transactions that were completed
+    /// by their set, see `Workload::read_modify_writes`.
+    pub read_modify_writes: usize,
+    /// Disk I/O of the measured window, `None` where it cannot be read.
+    pub io: Option<IoCounters>,
+    /// Size of the data directory before teardown, `None` for in-memory backends.
+    pub footprint: Option<DiskFootprint>,
+    /// Time per phase of the file backup test, `None` for the other backends.
+    pub phases: Option<BackupPhases>,
+    /// Outcome of the verification, `None` when it did not run. A divergence
+    /// from what the run should have read or left fails it.
+    #[serde(skip)]
+    pub verification: Option<Result<(), String>>,
+}
+
+impl RunResult {
+    /// Columns of `csv_record`, `duration` is written separately.
+    pub const CSV_COLUMNS: [&'static str; 30] = [
+        "ops_per_sec",
+        "p50_us",
+        "p90_us",
+        "p99_us",
+        "p999_us",
+        "max_us",
+        "bytes_set",
+        "read_modify_writes",
+        "read_bytes",
+        "write_bytes",
+        "read_syscalls",
+        "write_syscalls",
+        "write_amplification",
+        "disk_bytes",
+        "disk_files",
+        "wal_bytes",
+        "sst_bytes",
+        "snapshot_bytes",
+        "other_disk_bytes",
+        "restore_ms",
+        "execute_ms",
+        "backup_ms",
+        "restore_p50_us",
+        "restore_p99_us",
+        "execute_p50_us",
+        "execute_p99_us",
+        "backup_p50_us",
+        "backup_p99_us",
+        "verified",
+        "divergence",
+    ];
+
+    pub fn failed(&self) -> bool {
+        matches!(self.verification, Some(Err(_)))
+    }
+
+    /// Bytes written to disk per logical byte set.
+    pub fn write_amplification(&self) -> Option<f64> {
+        let io = self.io?;
+        (self.bytes_set > 0).then(|| io.write_bytes as f64 / self.bytes_set as f64)
+    }
+
+    /// The values of `CSV_COLUMNS`, I/O, disk, phase and verification
+    /// columns are empty when not measured.
+    pub fn csv_record(&self) -> Vec<String> {
+        let mut record = vec![
+            self.throughput.to_string(),
+            self.latency.p50.to_string(),
+            self.latency.p90.to_string(),
+            self.latency.p99.to_string(),
+            self.latency.p999.to_string(),
+            self.latency.max.to_string(),
+            self.bytes_set.to_string(),
+            self.read_modify_writes.to_string(),
+        ];
+        match self.io {
+            Some(io) => record.extend(io.csv_record()),
+            None => record.extend(IoCounters::CSV_COLUMNS.map(|_| String::new())),
+        }
+        record.push(self.write_amplification().map(|x| x.to_string()).unwrap_or_default());
+        match self.footprint {
+            Some(footprint) => record.extend(footprint.csv_record()),
+            None => record.extend(DiskFootprint::CSV_COLUMNS.map(|_| String::new())),
+        }
+        match self.phases {
+            Some(phases) => record.extend(phases.csv_record()),
+            None => record.extend(BackupPhases::CSV_COLUMNS.map(|_| String::new())),
+        }
+        match &self.verification {
+            Some(Ok(())) => record.extend(["true".to_string(), String::new()]),
+            Some(Err(divergence)) => record.extend(["false".to_string(), divergence.clone()]),
+            None => record.extend([String::new(), String::new()]),
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proc_io() {
+        let content = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\n\
+            read_bytes: 4096\nwrite_bytes: 323932160\ncancelled_write_bytes: 0\n";
+        let counters = IoCounters::parse(content).unwrap();
+        // rchar and wchar count page cache hits too, they are not taken.
+        assert_eq!(counters.read_bytes, 4096);
+        assert_eq!(counters.write_bytes, 323932160);
+        assert_eq!(counters.read_syscalls, 632687);
+        assert_eq!(counters.write_syscalls, 632675);
+
+        assert!(IoCounters::parse("read_bytes 4096\n").is_none());
+        assert!(IoCounters::parse("read_bytes: many\n").is_none());
+    }
+
+    #[test]
+    fn classifies_data_directory_files() {
+        let dir = std::env::temp_dir().join(format!("heart-footprint-{}", std::process::id()));
+        let files = [
+            ("000003.log", 10),
+            ("wal-1", 5),
+            ("sub/wal-2", 1),
+            ("000004.sst", 20),
+            // Checkpoints hold tables and logs too, they count as snapshots.
+            ("checkpoint/000004.sst", 7),
+            ("backup/test", 3),
+            ("MANIFEST-000001", 4),
+            ("OPTIONS-000005", 2),
+        ];
+        for (name, size) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0; size]).unwrap();
+        }
+
+        let footprint = DiskFootprint::measure(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(footprint.total_bytes, 52);
+        assert_eq!(footprint.file_count, 8);
+        assert_eq!(footprint.wal_bytes, 16);
+        assert_eq!(footprint.sst_bytes, 20);
+        assert_eq!(footprint.snapshot_bytes, 10);
+        assert_eq!(footprint.other_bytes, 6);
+    }
+
+    #[test]
+    fn parses_proc_stat() {
+        // The command name may contain spaces and parentheses itself.
+        let content =
+            "42 (heart (worker) 2) S 1 42 42 0 -1 4194304 100 0 0 0 250 75 0 0 20 0 7 0 12345\n";
+        let cpu = ProcessCpu::parse(content).unwrap();
+        assert_eq!(cpu.user_ms, 2500);
+        assert_eq!(cpu.system_ms, 750);
+        assert_eq!(cpu.threads, 7);
+
+        assert!(ProcessCpu::parse("42 (heart) S 1 42").is_none());
+    }
+}
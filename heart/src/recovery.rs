@@ -0,0 +1,181 @@
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+
+use crate::backend::{with_durable_backend, Backend, Durable};
+use crate::manifest::{self, unix_ms, Measurement, Recorder, RunManifest};
+use crate::metrics::DiskFootprint;
+use crate::stats::Summary;
+use crate::workload::{Operation, Workload, WorkloadConfig};
+
+/// How long a reopened backend gets to serve its first read.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Fills a store, drops it and measures how long it takes to come back.
+type RecoveryFunction = fn(&WorkloadConfig) -> Recovery;
+
+/// One measured restart.
+#[derive(Serialize)]
+pub struct Recovery {
+    /// Time spent filling the store, in milliseconds.
+    pub fill_ms: u128,
+    /// Time until `reopen` returned, in milliseconds.
+    pub reopen_ms: u128,
+    /// Time until the first read returned the last key written, in
+    /// milliseconds, `None` when it never did.
+    pub first_read_ms: Option<u128>,
+    /// Size of the data directory the store was reopened from.
+    pub footprint: DiskFootprint,
+}
+
+fn recovery_function(backend: Backend) -> RecoveryFunction {
+    with_durable_backend!(backend, B => measure::<B> as RecoveryFunction)
+}
+
+fn measure<B: Durable>(config: &WorkloadConfig) -> Recovery {
+    let workload = Workload::generate(config);
+    let last_key = match workload.transactions.iter().flatten().last() {
+        Some(Operation::Set(key, _)) => key.clone(),
+        _ => panic!("Recovery workloads end with an insert"),
+    };
+
+    let fill_start = Instant::now();
+    let backend = B::open();
+    backend.execute(B::build_transaction(workload.preload));
+    for operations in workload.transactions {
+        backend.execute(B::build_transaction(operations));
+    }
+    drop(backend);
+    let fill = fill_start.elapsed();
+
+    let footprint = DiskFootprint::measure(Path::new(B::DATA_DIR.unwrap()));
+
+    let start = Instant::now();
+    let backend = B::reopen();
+    let reopen = start.elapsed();
+    let first_read = loop {
+        if backend.read(std::slice::from_ref(&last_key))[0].is_some() {
+            break Some(start.elapsed());
+        }
+        if start.elapsed() > READ_TIMEOUT {
+            break None;
+        }
+    };
+    backend.teardown();
+
+    Recovery {
+        fill_ms: fill.as_millis(),
+        reopen_ms: reopen.as_millis(),
+        first_read_ms: first_read.map(|x| x.as_millis()),
+        footprint,
+    }
+}
+
+/// Restarts `backend` `repetitions` times from every configuration and writes
+/// the single restarts to `{file_name}.csv` and statistics over the time to
+/// the first read to `{file_name}_summary.csv`. Every restart is recorded
+/// to `{file_name}.jsonl` and `history`, and its CSV row carries the run id
+/// and the `Host::id`.
+pub fn run_recovery(
+    backend: Backend,
+    points: &[WorkloadConfig],
+    repetitions: usize,
+    output_dir: &Path,
+    file_name: &str,
+    history: &Path,
+) {
+    let function = recovery_function(backend);
+    let revision = manifest::revision(backend);
+    let recorder = Recorder::create(output_dir, file_name, history);
+
+    let mut wtr = csv::Writer::from_path(output_dir.join(format!("{}.csv", file_name))).unwrap();
+    let mut header = WorkloadConfig::CSV_COLUMNS.to_vec();
+    header.extend(["repetition", "run_id", "host", "fill_ms", "reopen_ms", "first_read_ms"]);
+    header.extend(DiskFootprint::CSV_COLUMNS);
+    wtr.write_record(header).unwrap();
+
+    let mut summary_wtr =
+        csv::Writer::from_path(output_dir.join(format!("{}_summary.csv", file_name))).unwrap();
+    let mut header = WorkloadConfig::CSV_COLUMNS.to_vec();
+    header.extend([
+        "repetitions",
+        "failed",
+        "mean_ms",
+        "median_ms",
+        "stddev_ms",
+        "ci95_low_ms",
+        "ci95_high_ms",
+    ]);
+    summary_wtr.write_record(header).unwrap();
+
+    for (point, config) in points.iter().enumerate() {
+        let mut times = Vec::new();
+        for repetition in 0..repetitions {
+            let started_at = SystemTime::now();
+            let recovery = function(config);
+            let ended_at = SystemTime::now();
+            let id = recorder.run_id(point, repetition);
+            recorder.record(&RunManifest {
+                run_id: &id,
+                backend: backend.name(),
+                revision: revision.as_deref(),
+                scenario: file_name,
+                repetition,
+                seed: config.seed,
+                workload: config,
+                host_id: &recorder.host_id,
+                host: &recorder.host,
+                started_at_ms: unix_ms(started_at),
+                ended_at_ms: unix_ms(ended_at),
+                result: Measurement::Recovery(&recovery),
+                verified: None,
+                divergence: None,
+            });
+            println!(
+                "Recovery of {} after {} transactions: reopen {} ms, first read {} ms",
+                backend.name(),
+                config.transaction_count,
+                recovery.reopen_ms,
+                recovery
+                    .first_read_ms
+                    .map(|x| x.to_string())
+                    .unwrap_or_else(|| "timed out".to_string())
+            );
+
+            let mut record = config.csv_record();
+            record.extend([
+                repetition.to_string(),
+                id,
+                recorder.host_id.clone(),
+                recovery.fill_ms.to_string(),
+                recovery.reopen_ms.to_string(),
+                recovery.first_read_ms.map(|x| x.to_string()).unwrap_or_default(),
+            ]);
+            record.extend(recovery.footprint.csv_record());
+            wtr.write_record(record).unwrap();
+            times.extend(recovery.first_read_ms.map(|x| x as f64));
+        }
+
+        let mut record = config.csv_record();
+        record.extend([times.len().to_string(), (repetitions - times.len()).to_string()]);
+        if times.is_empty() {
+            record.extend(["", "", "", "", ""].map(String::from));
+        } else {
+            let summary = Summary::of(&times);
+            record.extend(
+                [
+                    summary.mean,
+                    summary.median,
+                    summary.stddev,
+                    summary.ci95_low,
+                    summary.ci95_high,
+                ]
+                .map(|x| x.to_string()),
+            );
+        }
+        summary_wtr.write_record(record).unwrap();
+    }
+    wtr.flush().unwrap();
+    summary_wtr.flush().unwrap();
+}
@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::arrival::Arrival;
+use crate::backend::Backend;
+use crate::distribution::KeyDistribution;
+use crate::helpers::{DEFAULT_CV_THRESHOLD, DEFAULT_REPETITIONS, DEFAULT_WARMUP};
+use crate::workload::{WorkloadConfig, DEFAULT_KEY_SIZE, DEFAULT_VALUE_SIZE};
+
+/// A benchmark plan read from a TOML file, see `scenarios/` for examples.
+///
+/// Every backend runs the cartesian product of `write_percentages`,
+/// `transaction_sizes`, `transaction_counts`, `threads`, `arrivals`,
+/// `key_sizes` and `value_sizes`. Setting `total_operations` instead of
+/// `transaction_counts` derives the count from each size, like the
+/// `transaction-size` sweep does.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Prefix of the result files, followed by the backend name.
+    pub name: String,
+    pub backends: Vec<Backend>,
+    pub write_percentages: Vec<i32>,
+    /// Percentage of operations deleting a key, added to every point.
+    #[serde(default)]
+    pub delete_percentage: i32,
+    /// Percentage of read-modify-write operations, added to every point.
+    #[serde(default)]
+    pub read_modify_write_percentage: i32,
+    pub transaction_sizes: Vec<usize>,
+    #[serde(default)]
+    pub transaction_counts: Vec<usize>,
+    pub total_operations: Option<usize>,
+    /// Keys loaded before measuring, the transaction size when omitted.
+    pub record_count: Option<usize>,
+    /// Numbers of worker threads sharing the table.
+    #[serde(default = "default_threads")]
+    pub threads: Vec<usize>,
+    /// When transactions are issued, like `--arrival`.
+    #[serde(default = "default_arrivals")]
+    pub arrivals: Vec<Arrival>,
+    /// Key lengths in bytes.
+    #[serde(default = "default_key_sizes")]
+    pub key_sizes: Vec<usize>,
+    /// Value lengths in bytes.
+    #[serde(default = "default_value_sizes")]
+    pub value_sizes: Vec<usize>,
+    /// Runs per configuration that are thrown away before measuring.
+    #[serde(default = "default_warmup")]
+    pub warmup: usize,
+    /// Measured runs per configuration.
+    #[serde(default = "default_repetitions")]
+    pub repetitions: usize,
+    /// Flags configurations whose coefficient of variation is above this.
+    #[serde(default = "default_cv_threshold")]
+    pub cv_threshold: f64,
+    /// Whether measured runs are checked against an in-memory model.
+    #[serde(default)]
+    pub verify: bool,
+    /// Workload seed, a random one is picked when omitted.
+    pub seed: Option<u64>,
+    #[serde(default = "default_distribution")]
+    pub read_distribution: KeyDistribution,
+    #[serde(default = "default_distribution")]
+    pub write_distribution: KeyDistribution,
+    pub output_dir: Option<PathBuf>,
+}
+
+fn default_warmup() -> usize {
+    DEFAULT_WARMUP
+}
+
+fn default_repetitions() -> usize {
+    DEFAULT_REPETITIONS
+}
+
+fn default_cv_threshold() -> f64 {
+    DEFAULT_CV_THRESHOLD
+}
+
+fn default_threads() -> Vec<usize> {
+    vec![1]
+}
+
+fn default_arrivals() -> Vec<Arrival> {
+    vec![Arrival::Closed]
+}
+
+fn default_key_sizes() -> Vec<usize> {
+    vec![DEFAULT_KEY_SIZE]
+}
+
+fn default_value_sizes() -> Vec<usize> {
+    vec![DEFAULT_VALUE_SIZE]
+}
+
+fn default_distribution() -> KeyDistribution {
+    KeyDistribution::Sequential
+}
+
+impl Scenario {
+    pub fn from_file(path: &Path) -> Scenario {
+        let content = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Could not read scenario {}: {}", path.display(), e));
+        let scenario: Scenario = toml::from_str(&content)
+            .unwrap_or_else(|e| panic!("Invalid scenario {}: {}", path.display(), e));
+        scenario.validate();
+        scenario
+    }
+
+    fn validate(&self) {
+        if self.transaction_counts.is_empty() == self.total_operations.is_none() {
+            panic!(
+                "Scenario {} must set exactly one of transaction_counts and total_operations",
+                self.name
+            );
+        }
+        let other_percentages = self.delete_percentage + self.read_modify_write_percentage;
+        if self.delete_percentage < 0 || self.read_modify_write_percentage < 0 {
+            panic!("Scenario {} has a negative operation percentage", self.name);
+        }
+        if let Some(percentage) = self
+            .write_percentages
+            .iter()
+            .find(|&&x| x < 0 || x + other_percentages > 100)
+        {
+            panic!(
+                "Write percentage {} plus delete and read-modify-write percentages must be between 0 and 100",
+                percentage
+            );
+        }
+        if self.repetitions == 0 {
+            panic!("Scenario {} needs at least one repetition", self.name);
+        }
+        if self.threads.is_empty() || self.threads.contains(&0) {
+            panic!("Scenario {} needs at least one thread per run", self.name);
+        }
+        if self.arrivals.is_empty() {
+            panic!("Scenario {} needs at least one arrival", self.name);
+        }
+        if self.backends.contains(&Backend::FileBackup)
+            && (self.threads.iter().any(|&threads| threads > 1)
+                || self.arrivals.iter().any(|&arrival| arrival != Arrival::Closed))
+        {
+            panic!(
+                "Scenario {} runs {}, which only runs single threaded closed loops",
+                self.name,
+                Backend::FileBackup.name()
+            );
+        }
+        if self.key_sizes.is_empty() || self.value_sizes.is_empty() {
+            panic!("Scenario {} needs at least one key and value size", self.name);
+        }
+        if self.record_count == Some(0) {
+            panic!("Scenario {} needs a positive record count", self.name);
+        }
+        if self.transaction_sizes.contains(&0) {
+            panic!("Transaction sizes must be positive");
+        }
+    }
+
+    /// Expands the scenario into the configurations each backend runs, in order.
+    pub fn run_matrix(&self, seed: u64) -> Vec<WorkloadConfig> {
+        let mut shapes = Vec::new();
+        for &transaction_size in &self.transaction_sizes {
+            match self.total_operations {
+                Some(total_operations) => {
+                    shapes.push((transaction_size, total_operations / transaction_size))
+                }
+                None => shapes.extend(
+                    self.transaction_counts
+                        .iter()
+                        .map(|&transaction_count| (transaction_size, transaction_count)),
+                ),
+            }
+        }
+
+        let mut points = Vec::new();
+        for &write_percentage in &self.write_percentages {
+            for &(transaction_size, transaction_count) in &shapes {
+                for &threads in &self.threads {
+                    for &arrival in &self.arrivals {
+                        for &key_size in &self.key_sizes {
+                            for &value_size in &self.value_sizes {
+                                points.push(WorkloadConfig {
+                                    write_percentage,
+                                    delete_percentage: self.delete_percentage,
+                                    read_modify_write_percentage: self
+                                        .read_modify_write_percentage,
+                                    transaction_size,
+                                    transaction_count,
+                                    threads,
+                                    arrival,
+                                    seed,
+                                    read_distribution: self.read_distribution,
+                                    write_distribution: self.write_distribution,
+                                    record_count: self.record_count,
+                                    key_size,
+                                    value_size,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        points
+    }
+}
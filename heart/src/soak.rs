@@ -0,0 +1,342 @@
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+use sysinfo::{ProcessExt, ProcessRefreshKind, System, SystemExt};
+
+use crate::backend::{with_backend, Backend, StorageBackend};
+use crate::manifest::{self, unix_ms, Measurement, Recorder, RunManifest};
+use crate::metrics::{DiskFootprint, Latencies, LatencySummary};
+use crate::workload::{Workload, WorkloadConfig};
+
+/// Windows needed before a run's trends are judged.
+const MIN_WINDOWS: usize = 4;
+/// Share of the steps between two windows that have to grow for a series to
+/// count as growing monotonically.
+const MONOTONIC_FRACTION: f64 = 0.9;
+/// Growth from the first to the last window a monotonic series needs to be
+/// flagged.
+const GROWTH_THRESHOLD: f64 = 0.1;
+/// Drop of the mean throughput from the first to the last quarter of the
+/// windows that is flagged.
+const DEGRADATION_THRESHOLD: f64 = 0.1;
+
+/// Runs the workload until the duration is over, handing every closed window
+/// to the callback.
+type SoakFunction = fn(&WorkloadConfig, Duration, Duration, &mut dyn FnMut(Window));
+
+/// What a soak run measured over one interval.
+#[derive(Serialize)]
+pub struct Window {
+    /// Seconds since the preload at the end of the window.
+    pub elapsed_s: f64,
+    pub transactions: usize,
+    /// Operations per second spent in `execute`, regenerating the workload
+    /// between batches is not counted.
+    pub throughput: f64,
+    pub latency: LatencySummary,
+    /// Resident set of this process at the end of the window.
+    pub rss_bytes: u64,
+    /// Size of the data directory at the end of the window, `None` for
+    /// backends that keep nothing on disk.
+    pub footprint: Option<DiskFootprint>,
+}
+
+impl Window {
+    pub const CSV_COLUMNS: [&'static str; 10] = [
+        "window",
+        "elapsed_s",
+        "transactions",
+        "ops_per_sec",
+        "p50_us",
+        "p90_us",
+        "p99_us",
+        "p999_us",
+        "max_us",
+        "rss_bytes",
+    ];
+
+    /// The values of `CSV_COLUMNS` followed by the `DiskFootprint` columns,
+    /// which are empty when nothing was measured.
+    pub fn csv_record(&self, index: usize) -> Vec<String> {
+        let mut record = vec![
+            index.to_string(),
+            self.elapsed_s.to_string(),
+            self.transactions.to_string(),
+            self.throughput.to_string(),
+            self.latency.p50.to_string(),
+            self.latency.p90.to_string(),
+            self.latency.p99.to_string(),
+            self.latency.p999.to_string(),
+            self.latency.max.to_string(),
+            self.rss_bytes.to_string(),
+        ];
+        match self.footprint {
+            Some(footprint) => record.extend(footprint.csv_record()),
+            None => record.extend(DiskFootprint::CSV_COLUMNS.map(|_| String::new())),
+        }
+        record
+    }
+}
+
+/// A series checked over the windows of a run.
+pub struct Trend {
+    pub metric: &'static str,
+    /// `growth` or `degradation`.
+    pub check: &'static str,
+    pub first: f64,
+    pub last: f64,
+    pub flagged: bool,
+}
+
+fn soak_function(backend: Backend) -> SoakFunction {
+    with_backend!(backend, B => soak::<B> as SoakFunction)
+}
+
+/// Executes batches of `config.transaction_count` transactions back to back,
+/// drawing every batch after the first from the next seed so the same keys
+/// keep being read and overwritten.
+fn soak<B: StorageBackend>(
+    config: &WorkloadConfig,
+    duration: Duration,
+    interval: Duration,
+    on_window: &mut dyn FnMut(Window),
+) {
+    let mut workload = Workload::generate(config);
+    let backend = B::open();
+    backend.execute(B::build_transaction(std::mem::take(&mut workload.preload)));
+
+    let mut sys = System::new();
+    let pid = sysinfo::get_current_pid().unwrap();
+
+    let start = Instant::now();
+    let mut window_start = start;
+    let mut latencies = Latencies::new();
+    let mut transactions = 0;
+    let mut busy = Duration::ZERO;
+    let mut batch = 0;
+    'soak: loop {
+        for operations in workload.transactions {
+            let transaction = B::build_transaction(operations);
+            let transaction_start = Instant::now();
+            backend.execute(transaction);
+            let latency = transaction_start.elapsed();
+            latencies.record(latency);
+            busy += latency;
+            transactions += 1;
+
+            if window_start.elapsed() < interval {
+                continue;
+            }
+            sys.refresh_process_specifics(pid, ProcessRefreshKind::new());
+            on_window(Window {
+                elapsed_s: start.elapsed().as_secs_f64(),
+                transactions,
+                throughput: (transactions * config.transaction_size) as f64 / busy.as_secs_f64(),
+                latency: latencies.summary(),
+                rss_bytes: sys.process(pid).map(|process| process.memory()).unwrap_or_default(),
+                footprint: B::DATA_DIR.map(|dir| DiskFootprint::measure(Path::new(dir))),
+            });
+            if start.elapsed() >= duration {
+                break 'soak;
+            }
+            window_start = Instant::now();
+            latencies = Latencies::new();
+            transactions = 0;
+            busy = Duration::ZERO;
+        }
+
+        batch += 1;
+        workload = Workload::generate(&WorkloadConfig {
+            seed: config.seed.wrapping_add(batch),
+            ..config.clone()
+        });
+    }
+    backend.teardown();
+}
+
+/// Whether `values` grew in nearly every step and by more than
+/// `GROWTH_THRESHOLD` overall.
+fn growth(metric: &'static str, values: &[f64]) -> Trend {
+    let (first, last) = (values[0], values[values.len() - 1]);
+    let steps = values.windows(2).filter(|pair| pair[1] > pair[0]).count();
+    Trend {
+        metric,
+        check: "growth",
+        first,
+        last,
+        flagged: steps as f64 >= MONOTONIC_FRACTION * (values.len() - 1) as f64
+            && last > first * (1.0 + GROWTH_THRESHOLD),
+    }
+}
+
+/// Whether the mean of the last quarter of `values` dropped by more than
+/// `DEGRADATION_THRESHOLD` below the mean of the first quarter.
+fn degradation(metric: &'static str, values: &[f64]) -> Trend {
+    let quarter = (values.len() / 4).max(1);
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let first = mean(&values[..quarter]);
+    let last = mean(&values[values.len() - quarter..]);
+    Trend {
+        metric,
+        check: "degradation",
+        first,
+        last,
+        flagged: last < first * (1.0 - DEGRADATION_THRESHOLD),
+    }
+}
+
+fn trends(windows: &[Window]) -> Vec<Trend> {
+    let series = |f: fn(&Window) -> f64| windows.iter().map(f).collect::<Vec<_>>();
+    let mut trends = vec![
+        degradation("ops_per_sec", &series(|w| w.throughput)),
+        growth("p99_us", &series(|w| w.latency.p99 as f64)),
+        growth("rss_bytes", &series(|w| w.rss_bytes as f64)),
+    ];
+    if windows.iter().all(|w| w.footprint.is_some()) {
+        trends.extend([
+            growth("disk_bytes", &series(|w| w.footprint.unwrap().total_bytes as f64)),
+            growth("wal_bytes", &series(|w| w.footprint.unwrap().wal_bytes as f64)),
+        ]);
+    }
+    trends
+}
+
+/// Soaks `backend` with `config` on a single worker in a closed loop for
+/// `duration`, writing a row per `interval` to `{file_name}.csv`, with its run
+/// id and the `Host::id`, and recording it to `{file_name}.jsonl` and
+/// `history` as it goes, and the checked trends to `{file_name}_trends.csv`.
+pub fn run_soak(
+    backend: Backend,
+    config: &WorkloadConfig,
+    duration: Duration,
+    interval: Duration,
+    output_dir: &Path,
+    file_name: &str,
+    history: &Path,
+) {
+    assert!(!interval.is_zero(), "The soak interval has to be positive");
+    let function = soak_function(backend);
+    let revision = manifest::revision(backend);
+    let recorder = Recorder::create(output_dir, file_name, history);
+
+    let mut wtr = csv::Writer::from_path(output_dir.join(format!("{}.csv", file_name))).unwrap();
+    let mut header = WorkloadConfig::CSV_COLUMNS.to_vec();
+    header.extend(["run_id", "host"]);
+    header.extend(Window::CSV_COLUMNS);
+    header.extend(DiskFootprint::CSV_COLUMNS);
+    wtr.write_record(header).unwrap();
+
+    println!("Soaking {} for {:?}, {}", backend.name(), duration, config);
+    let mut windows = Vec::new();
+    let mut started_at = SystemTime::now();
+    function(config, duration, interval, &mut |window| {
+        let ended_at = SystemTime::now();
+        let id = recorder.run_id(0, windows.len());
+        recorder.record(&RunManifest {
+            run_id: &id,
+            backend: backend.name(),
+            revision: revision.as_deref(),
+            scenario: file_name,
+            repetition: windows.len(),
+            seed: config.seed,
+            workload: config,
+            host_id: &recorder.host_id,
+            host: &recorder.host,
+            started_at_ms: unix_ms(started_at),
+            ended_at_ms: unix_ms(ended_at),
+            result: Measurement::SoakWindow(&window),
+            verified: None,
+            divergence: None,
+        });
+        started_at = ended_at;
+        println!(
+            "Window {} of {}: {:.0} ops/sec, p99 {} us, rss {} bytes",
+            windows.len(),
+            backend.name(),
+            window.throughput,
+            window.latency.p99,
+            window.rss_bytes
+        );
+        let mut record = config.csv_record();
+        record.extend([id, recorder.host_id.clone()]);
+        record.extend(window.csv_record(windows.len()));
+        wtr.write_record(record).unwrap();
+        // A run of hours should leave its windows behind even when killed.
+        wtr.flush().unwrap();
+        windows.push(window);
+    });
+
+    if windows.len() < MIN_WINDOWS {
+        println!(
+            "Soak of {} closed {} windows, at least {} are needed to judge trends",
+            backend.name(),
+            windows.len(),
+            MIN_WINDOWS
+        );
+        return;
+    }
+
+    let mut wtr =
+        csv::Writer::from_path(output_dir.join(format!("{}_trends.csv", file_name))).unwrap();
+    wtr.write_record(["metric", "check", "first", "last", "flagged"]).unwrap();
+    let trends = trends(&windows);
+    for trend in &trends {
+        if trend.flagged {
+            println!(
+                "Soak of {} flagged {} {}: {} in the first windows, {} in the last",
+                backend.name(),
+                trend.metric,
+                trend.check,
+                trend.first,
+                trend.last
+            );
+        }
+        wtr.write_record([
+            trend.metric.to_string(),
+            trend.check.to_string(),
+            trend.first.to_string(),
+            trend.last.to_string(),
+            trend.flagged.to_string(),
+        ])
+        .unwrap();
+    }
+    wtr.flush().unwrap();
+    if trends.iter().all(|trend| !trend.flagged) {
+        println!("Soak of {} showed no drift", backend.name());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn growth_flags_steady_leaks_only() {
+        let leak = (0..10).map(|i| 100.0 + 3.0 * i as f64).collect::<Vec<_>>();
+        assert!(growth("rss_bytes", &leak).flagged);
+
+        // Noise around a level and a single jump, like a cache filling up.
+        let noise = [100.0, 105.0, 98.0, 104.0, 99.0, 106.0, 101.0, 103.0];
+        assert!(!growth("rss_bytes", &noise).flagged);
+        let mut jump = vec![100.0; 9];
+        jump.push(200.0);
+        assert!(!growth("rss_bytes", &jump).flagged);
+
+        // Growing in every step but by less than the threshold overall.
+        let creep = (0..10).map(|i| 100.0 + 0.5 * i as f64).collect::<Vec<_>>();
+        assert!(!growth("rss_bytes", &creep).flagged);
+    }
+
+    #[test]
+    fn degradation_compares_the_first_and_last_quarter() {
+        let slowing = [1000.0, 1000.0, 980.0, 950.0, 920.0, 900.0, 850.0, 850.0];
+        let trend = degradation("ops_per_sec", &slowing);
+        assert_eq!((trend.first, trend.last), (1000.0, 850.0));
+        assert!(trend.flagged);
+
+        // A slow stretch in the middle, e.g. a compaction, recovers.
+        let dip = [1000.0, 1000.0, 700.0, 600.0, 700.0, 950.0, 1000.0, 990.0];
+        assert!(!degradation("ops_per_sec", &dip).flagged);
+    }
+}
@@ -0,0 +1,122 @@
+/// Two-sided 95% critical values of Student's t distribution for 1 to 30
+/// degrees of freedom. Larger samples use the normal approximation.
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// Descriptive statistics over the repetitions of one data point.
+#[derive(Clone, Copy, Debug)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation, zero for a single sample.
+    pub stddev: f64,
+    pub ci95_low: f64,
+    pub ci95_high: f64,
+    /// Coefficient of variation, `stddev / mean`.
+    pub cv: f64,
+}
+
+impl Summary {
+    pub fn of(samples: &[f64]) -> Summary {
+        assert!(!samples.is_empty(), "Cannot summarize an empty sample");
+
+        let count = samples.len();
+        let mean = samples.iter().sum::<f64>() / count as f64;
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        // Both indices point at the middle element for an odd count.
+        let median = (sorted[(count - 1) / 2] + sorted[count / 2]) / 2.0;
+
+        let stddev = if count > 1 {
+            let variance =
+                samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (count - 1) as f64;
+            variance.sqrt()
+        } else {
+            0.0
+        };
+
+        let t = if count > 1 {
+            T_95.get(count - 2).copied().unwrap_or(1.96)
+        } else {
+            0.0
+        };
+        let margin = t * stddev / (count as f64).sqrt();
+
+        let cv = if mean > 0.0 { stddev / mean } else { 0.0 };
+
+        Summary {
+            count,
+            mean,
+            median,
+            stddev,
+            ci95_low: mean - margin,
+            ci95_high: mean + margin,
+            cv,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn single_sample_has_no_spread() {
+        let summary = Summary::of(&[5.0]);
+        assert_eq!(summary.count, 1);
+        assert_close(summary.median, 5.0);
+        assert_close(summary.stddev, 0.0);
+        assert_close(summary.ci95_low, 5.0);
+        assert_close(summary.ci95_high, 5.0);
+    }
+
+    #[test]
+    fn median_of_even_count_averages_middle_elements() {
+        let summary = Summary::of(&[4.0, 1.0, 3.0, 2.0]);
+        assert_close(summary.mean, 2.5);
+        assert_close(summary.median, 2.5);
+        assert_close(summary.median, Summary::of(&[1.0, 2.0, 10.0, 3.0]).median);
+        assert_close(Summary::of(&[3.0, 1.0, 2.0]).median, 2.0);
+    }
+
+    #[test]
+    fn confidence_interval_uses_degrees_of_freedom() {
+        // One degree of freedom, the stddev and sqrt(count) cancel out.
+        let summary = Summary::of(&[1.0, 3.0]);
+        assert_close(summary.stddev, 2f64.sqrt());
+        assert_close(summary.ci95_high - summary.mean, 12.706);
+
+        // 30 degrees of freedom is the last entry of the table.
+        let samples = (0..31).map(|x| x as f64).collect::<Vec<_>>();
+        let summary = Summary::of(&samples);
+        let margin = 2.042 * summary.stddev / 31f64.sqrt();
+        assert_close(summary.ci95_high - summary.mean, margin);
+
+        // Past the table the normal approximation takes over.
+        let samples = (0..32).map(|x| x as f64).collect::<Vec<_>>();
+        let summary = Summary::of(&samples);
+        let margin = 1.96 * summary.stddev / 32f64.sqrt();
+        assert_close(summary.ci95_high - summary.mean, margin);
+        assert_close(summary.mean - summary.ci95_low, margin);
+    }
+
+    #[test]
+    fn cv_is_zero_without_a_positive_mean() {
+        assert_close(Summary::of(&[0.0, 0.0]).cv, 0.0);
+        assert_close(Summary::of(&[1.0, 3.0]).cv, 2f64.sqrt() / 2.0);
+    }
+}
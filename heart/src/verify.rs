@@ -0,0 +1,210 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::backend::{Response, StorageBackend};
+use crate::workload::{Key, Operation, Value};
+
+/// The transactions a worker executed, in order, as their index with the
+/// values their gets returned.
+pub type Executed = Vec<(usize, Vec<Option<Value>>)>;
+
+/// Copies the values out of the responses a worker kept, with the queries of
+/// their gets, once the run is timed.
+pub fn executed<Q, R: Response<Q>>(responses: &[(usize, R, Vec<Q>)]) -> Executed {
+    responses
+        .iter()
+        .map(|(index, response, queries)| {
+            let values = queries
+                .iter()
+                .map(|query| response.value(query).cloned())
+                .collect();
+            (*index, values)
+        })
+        .collect()
+}
+
+/// Checks a measured run against a `HashMap` model of its workload, before
+/// `backend` is torn down. Every get has to return what its key held before
+/// the transaction, and afterwards every preloaded key and every key ever set
+/// or removed has to read back as the run left it.
+///
+/// Workers run concurrently, so with more than one the order of their
+/// transactions is open. A get then only has to return some state its key
+/// was in, and a key may read back as any worker last left it. Fails with
+/// the first divergence.
+pub fn verify<B: StorageBackend>(
+    backend: &B,
+    preload: &[Operation],
+    transactions: &[Vec<Operation>],
+    workers: &[Executed],
+) -> Result<(), String> {
+    let mut initial = HashMap::<&Key, &Value>::new();
+    for operation in preload {
+        if let Operation::Set(key, value) = operation {
+            initial.insert(key, value);
+        }
+    }
+
+    // Every state a key is put in, and what each worker last left in it.
+    let mut states = HashMap::<&Key, HashSet<Option<&Value>>>::new();
+    let mut left = HashMap::<&Key, Vec<Option<&Value>>>::new();
+    for executed in workers {
+        let mut last = HashMap::new();
+        for (index, _) in executed {
+            for operation in &transactions[*index] {
+                let (key, state) = match operation {
+                    Operation::Set(key, value) => (key, Some(value)),
+                    Operation::Remove(key) => (key, None),
+                    Operation::Get(_) => continue,
+                };
+                states.entry(key).or_default().insert(state);
+                last.insert(key, state);
+            }
+        }
+        for (key, state) in last {
+            left.entry(key).or_default().push(state);
+        }
+    }
+    // Preloaded keys no transaction touched have to keep their value.
+    for (&key, &value) in &initial {
+        left.entry(key).or_insert_with(|| vec![Some(value)]);
+    }
+
+    let mut shadow = initial.clone();
+    for executed in workers {
+        for (index, values) in executed {
+            let operations = &transactions[*index];
+            let gets = operations.iter().filter_map(|operation| match operation {
+                Operation::Get(key) => Some(key),
+                _ => None,
+            });
+            for (key, value) in gets.zip(values) {
+                let value = value.as_ref();
+                // A transaction touches every key once, so its gets see the
+                // state before it.
+                let expected = shadow.get(key).copied();
+                if workers.len() == 1 && value != expected {
+                    return Err(format!(
+                        "get of {} in transaction {} returned {:?}, expected {:?}",
+                        key, index, value, expected
+                    ));
+                }
+                if value != initial.get(key).copied()
+                    && !states.get(key).is_some_and(|states| states.contains(&value))
+                {
+                    return Err(format!(
+                        "get of {} in transaction {} returned {:?}, which it never held",
+                        key, index, value
+                    ));
+                }
+            }
+            for operation in operations {
+                match operation {
+                    Operation::Set(key, value) => {
+                        shadow.insert(key, value);
+                    }
+                    Operation::Remove(key) => {
+                        shadow.remove(key);
+                    }
+                    Operation::Get(_) => {}
+                }
+            }
+        }
+    }
+
+    let mut keys = left.keys().map(|&key| key.clone()).collect::<Vec<_>>();
+    keys.sort();
+    let values = backend.read(&keys);
+    for (key, value) in keys.iter().zip(values) {
+        if !left[key].contains(&value.as_ref()) {
+            return Err(format!(
+                "{} reads back as {:?} after the run, expected one of {:?}",
+                key, value, left[key]
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::DashMap;
+
+    fn set(key: &str, value: u8) -> Operation {
+        Operation::Set(key.to_string(), vec![value])
+    }
+
+    fn get(key: &str) -> Operation {
+        Operation::Get(key.to_string())
+    }
+
+    fn preload() -> Vec<Operation> {
+        vec![set("a", 1), set("b", 2), set("c", 3)]
+    }
+
+    fn transactions() -> Vec<Vec<Operation>> {
+        vec![
+            vec![get("a"), set("b", 5)],
+            vec![get("b"), Operation::Remove("a".to_string())],
+        ]
+    }
+
+    /// Executes `transactions` in order against a table loaded with `preload`.
+    fn run(preload: &[Operation], transactions: &[Vec<Operation>]) -> (DashMap, Executed) {
+        let backend = DashMap::open();
+        backend.execute(DashMap::build_transaction(preload.to_vec()));
+        let responses = transactions
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(index, operations)| {
+                let (transaction, queries) = DashMap::build_transaction_with_queries(operations);
+                (index, backend.execute(transaction), queries)
+            })
+            .collect::<Vec<_>>();
+        (backend, executed(&responses))
+    }
+
+    #[test]
+    fn accepts_a_faithful_run() {
+        let (backend, executed) = run(&preload(), &transactions());
+        assert_eq!(executed[1].1, vec![Some(vec![5])]);
+        assert_eq!(verify(&backend, &preload(), &transactions(), &[executed]), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_stale_get() {
+        let (backend, mut executed) = run(&preload(), &transactions());
+        executed[1].1[0] = Some(vec![2]);
+        let error = verify(&backend, &preload(), &transactions(), &[executed]).unwrap_err();
+        assert!(error.starts_with("get of b in transaction 1"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_lost_untouched_key() {
+        let (backend, executed) = run(&preload(), &transactions());
+        backend.execute(DashMap::build_transaction(vec![Operation::Remove("c".to_string())]));
+        let error = verify(&backend, &preload(), &transactions(), &[executed]).unwrap_err();
+        assert!(error.starts_with("c reads back as None"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_lost_write() {
+        let (backend, executed) = run(&preload(), &transactions());
+        backend.execute(DashMap::build_transaction(vec![set("b", 2)]));
+        assert!(verify(&backend, &preload(), &transactions(), &[executed]).is_err());
+    }
+
+    #[test]
+    fn concurrent_gets_may_see_any_state_of_their_key() {
+        // The second worker's get ran after the first worker's set.
+        let (backend, executed) = run(&preload(), &transactions());
+        let workers = [vec![executed[0].clone()], vec![executed[1].clone()]];
+        assert_eq!(verify(&backend, &preload(), &transactions(), &workers), Ok(()));
+
+        let mut workers = workers;
+        workers[1][0].1[0] = Some(vec![7]);
+        let error = verify(&backend, &preload(), &transactions(), &workers).unwrap_err();
+        assert!(error.ends_with("which it never held"), "{}", error);
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::arrival::Arrival;
+use crate::distribution::{KeyChooser, KeyDistribution};
+
+pub type Key = String;
+pub type Value = Vec<u8>;
+
+pub const DEFAULT_KEY_SIZE: usize = 16;
+pub const DEFAULT_VALUE_SIZE: usize = 8;
+
+/// Parameters of a single benchmark run.
+///
+/// The percentages split the operations into plain writes, deletes and
+/// read-modify-writes, whatever is left over are reads.
+#[derive(Clone, Debug, Serialize)]
+pub struct WorkloadConfig {
+    pub write_percentage: i32,
+    /// Deletes of existing keys, picked like writes.
+    pub delete_percentage: i32,
+    /// Gets of an existing key, picked like reads, followed by a set of it
+    /// that takes the first slots of the next transaction. The last
+    /// transaction only reads.
+    pub read_modify_write_percentage: i32,
+    pub transaction_size: usize,
+    pub transaction_count: usize,
+    /// Worker threads sharing the table, each executing a contiguous slice
+    /// of the transactions, or every `threads`-th one in open loops.
+    pub threads: usize,
+    /// When transactions are issued. Open loops measure latency from the
+    /// intended start, so time spent queueing behind a slow transaction
+    /// counts.
+    pub arrival: Arrival,
+    /// Seed the operations are drawn from. Runs with the same configuration
+    /// and seed execute the same operations, on every backend.
+    pub seed: u64,
+    pub read_distribution: KeyDistribution,
+    pub write_distribution: KeyDistribution,
+    /// Keys loaded before measuring, `transaction_size` when `None`.
+    pub record_count: Option<usize>,
+    /// Length of every key in bytes. Keys only grow beyond it when their
+    /// index does not fit.
+    pub key_size: usize,
+    /// Length of every value in bytes.
+    pub value_size: usize,
+}
+
+impl WorkloadConfig {
+    pub const CSV_COLUMNS: [&'static str; 14] = [
+        "write_percentage",
+        "delete_percentage",
+        "read_modify_write_percentage",
+        "transaction_size",
+        "transaction_count",
+        "threads",
+        "arrival",
+        "offered_ops_per_sec",
+        "record_count",
+        "key_size",
+        "value_size",
+        "seed",
+        "read_distribution",
+        "write_distribution",
+    ];
+
+    pub fn record_count(&self) -> usize {
+        self.record_count.unwrap_or(self.transaction_size)
+    }
+
+    /// A workload that only inserts new keys, so the table holds exactly the
+    /// transactions that made it.
+    pub fn inserts(
+        seed: u64,
+        transaction_size: usize,
+        transaction_count: usize,
+        value_size: usize,
+    ) -> Self {
+        WorkloadConfig {
+            write_percentage: 100,
+            delete_percentage: 0,
+            read_modify_write_percentage: 0,
+            transaction_size,
+            transaction_count,
+            threads: 1,
+            arrival: Arrival::Closed,
+            seed,
+            read_distribution: KeyDistribution::Sequential,
+            write_distribution: KeyDistribution::Sequential,
+            record_count: None,
+            key_size: DEFAULT_KEY_SIZE,
+            value_size,
+        }
+    }
+
+    /// Percentage of operations that are plain reads.
+    pub fn read_percentage(&self) -> i32 {
+        100 - self.write_percentage - self.delete_percentage - self.read_modify_write_percentage
+    }
+
+    /// The values of `CSV_COLUMNS`.
+    pub fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.write_percentage.to_string(),
+            self.delete_percentage.to_string(),
+            self.read_modify_write_percentage.to_string(),
+            self.transaction_size.to_string(),
+            self.transaction_count.to_string(),
+            self.threads.to_string(),
+            self.arrival.name().to_string(),
+            self.arrival.offered_load().map(|x| x.to_string()).unwrap_or_default(),
+            self.record_count().to_string(),
+            self.key_size.to_string(),
+            self.value_size.to_string(),
+            self.seed.to_string(),
+            self.read_distribution.to_string(),
+            self.write_distribution.to_string(),
+        ]
+    }
+}
+
+impl fmt::Display for WorkloadConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "write percentage: {}, delete percentage {}, read-modify-write percentage {}, transaction_size {}, transaction_count {}, threads {}, arrival {}, record_count {}, key_size {}, value_size {}, seed {}, read distribution {}, write distribution {}",
+            self.write_percentage,
+            self.delete_percentage,
+            self.read_modify_write_percentage,
+            self.transaction_size,
+            self.transaction_count,
+            self.threads,
+            self.arrival,
+            self.record_count(),
+            self.key_size,
+            self.value_size,
+            self.seed,
+            self.read_distribution,
+            self.write_distribution
+        )
+    }
+}
+
+/// Picks a seed for invocations that did not ask for a specific one.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// A single operation inside a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Set(Key, Value),
+    Get(Key),
+    Remove(Key),
+}
+
+/// What a single drawn operation of the mix does.
+enum OperationKind {
+    Write,
+    Delete,
+    ReadModifyWrite,
+    Read,
+}
+
+impl OperationKind {
+    fn draw(rng: &mut impl Rng, config: &WorkloadConfig) -> Self {
+        let mut rand_value: f64 = rng.gen_range(0.0..100.0);
+        for (percentage, kind) in [
+            (config.write_percentage, OperationKind::Write),
+            (config.delete_percentage, OperationKind::Delete),
+            (config.read_modify_write_percentage, OperationKind::ReadModifyWrite),
+        ] {
+            if rand_value < percentage.into() {
+                return kind;
+            }
+            rand_value -= percentage as f64;
+        }
+        OperationKind::Read
+    }
+}
+
+/// The operations of one benchmark run, before they are turned into
+/// backend specific transactions.
+pub struct Workload {
+    /// Executed before measuring, fills the table with the keys read later.
+    pub preload: Vec<Operation>,
+    pub transactions: Vec<Vec<Operation>>,
+    /// Read-modify-writes whose set made it into a transaction, the ones
+    /// drawn in the last transaction stay plain reads.
+    pub read_modify_writes: usize,
+}
+
+impl Workload {
+    /// Draws the operations of `config` from a generator seeded with
+    /// `config.seed`. ChaCha8 is used instead of `StdRng` because its output
+    /// is stable across `rand` releases, so old seeds stay replayable.
+    pub fn generate(config: &WorkloadConfig) -> Workload {
+        let percentages = [
+            config.write_percentage,
+            config.delete_percentage,
+            config.read_modify_write_percentage,
+        ];
+        if percentages.iter().any(|x| !(0..=100).contains(x)) || config.read_percentage() < 0 {
+            panic!("Operation percentages must be between 0 and 100 and add up to at most 100");
+        }
+
+        let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+
+        let preload = (0..config.record_count())
+            .map(|i| Operation::Set(key(i, config.key_size), value(&mut rng, config.value_size)))
+            .collect();
+        let mut key_count = config.record_count();
+
+        let mut reads = KeyChooser::for_reads(config.read_distribution);
+        let mut writes = KeyChooser::for_writes(config.write_distribution);
+        // Deletes only pick existing keys, the oldest first when sequential.
+        let mut deletes = KeyChooser::for_reads(config.write_distribution);
+
+        // Keys read by read-modify-writes, set in the next transaction.
+        let mut modified = Vec::new();
+        let mut read_modify_writes = 0;
+
+        let mut transactions = Vec::with_capacity(config.transaction_count);
+        for transaction in 0..config.transaction_count {
+            let last = transaction + 1 == config.transaction_count;
+            let mut operations = Vec::with_capacity(config.transaction_size);
+            // The backends reject transactions touching a key twice.
+            let mut used = HashSet::with_capacity(config.transaction_size);
+            for index in modified.drain(..) {
+                read_modify_writes += 1;
+                used.insert(index);
+                operations.push(Operation::Set(
+                    key(index, config.key_size),
+                    value(&mut rng, config.value_size),
+                ));
+            }
+            while operations.len() < config.transaction_size {
+                match OperationKind::draw(&mut rng, config) {
+                    OperationKind::Write => {
+                        let index = writes.next_unused(&mut rng, key_count, &used);
+                        used.insert(index);
+                        if index == key_count {
+                            key_count += 1;
+                        }
+                        operations.push(Operation::Set(
+                            key(index, config.key_size),
+                            value(&mut rng, config.value_size),
+                        ));
+                    }
+                    OperationKind::Delete => {
+                        let index = deletes.next_unused(&mut rng, key_count, &used);
+                        used.insert(index);
+                        operations.push(Operation::Remove(key(index, config.key_size)));
+                    }
+                    OperationKind::ReadModifyWrite => {
+                        let index = reads.next_unused(&mut rng, key_count, &used);
+                        used.insert(index);
+                        operations.push(Operation::Get(key(index, config.key_size)));
+                        if !last {
+                            modified.push(index);
+                        }
+                    }
+                    OperationKind::Read => {
+                        let index = reads.next_unused(&mut rng, key_count, &used);
+                        used.insert(index);
+                        operations.push(Operation::Get(key(index, config.key_size)));
+                    }
+                }
+            }
+            transactions.push(operations);
+        }
+
+        Workload {
+            preload,
+            transactions,
+            read_modify_writes,
+        }
+    }
+
+    /// Logical bytes, keys plus values, set by the measured transactions.
+    pub fn bytes_set(&self) -> u64 {
+        self.transactions.iter().map(|operations| bytes_set(operations)).sum()
+    }
+}
+
+/// Logical bytes, keys plus values, set by `operations`.
+pub fn bytes_set(operations: &[Operation]) -> u64 {
+    operations
+        .iter()
+        .map(|operation| match operation {
+            Operation::Set(key, value) => (key.len() + value.len()) as u64,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// The key inserted as the `index`-th into the table, zero padded to `size`.
+fn key(index: usize, size: usize) -> Key {
+    format!("k{:0width$}", index, width = size.saturating_sub(1))
+}
+
+/// Random bytes, so values do not compress better than real payloads would.
+fn value(rng: &mut impl Rng, size: usize) -> Value {
+    let mut value = vec![0; size];
+    rng.fill(&mut value[..]);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transactions_touch_every_key_once() {
+        for distribution in ["sequential", "uniform", "zipfian", "hotspot:0.01:0.9", "latest"] {
+            let distribution: KeyDistribution = distribution.parse().unwrap();
+            let config = WorkloadConfig {
+                write_percentage: 30,
+                delete_percentage: 20,
+                read_modify_write_percentage: 20,
+                read_distribution: distribution,
+                write_distribution: distribution,
+                record_count: Some(100),
+                ..WorkloadConfig::inserts(42, 60, 20, DEFAULT_VALUE_SIZE)
+            };
+            for operations in Workload::generate(&config).transactions {
+                let mut keys = HashSet::new();
+                for operation in operations {
+                    let key = match operation {
+                        Operation::Set(key, _) | Operation::Get(key) | Operation::Remove(key) => key,
+                    };
+                    assert!(keys.insert(key.clone()), "{} repeats {}", distribution, key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn read_modify_writes_set_in_the_next_transaction() {
+        let config = WorkloadConfig {
+            write_percentage: 0,
+            read_modify_write_percentage: 100,
+            read_distribution: KeyDistribution::Uniform,
+            record_count: Some(100),
+            ..WorkloadConfig::inserts(42, 10, 5, DEFAULT_VALUE_SIZE)
+        };
+        let workload = Workload::generate(&config);
+        // The sets fill the second and fourth transaction, the reads of the
+        // last one are never set.
+        assert_eq!(workload.read_modify_writes, 20);
+        for pair in workload.transactions.windows(2) {
+            let read = pair[0]
+                .iter()
+                .filter_map(|operation| match operation {
+                    Operation::Get(key) => Some(key),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let set = pair[1]
+                .iter()
+                .filter_map(|operation| match operation {
+                    Operation::Set(key, _) => Some(key),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(read, set);
+        }
+    }
+
+    #[test]
+    fn keys_and_values_have_the_configured_sizes() {
+        let config = WorkloadConfig {
+            write_percentage: 50,
+            key_size: 32,
+            value_size: 100,
+            ..WorkloadConfig::inserts(42, 20, 5, DEFAULT_VALUE_SIZE)
+        };
+        let workload = Workload::generate(&config);
+        let transactions = workload.transactions.iter().flatten();
+        for operation in workload.preload.iter().chain(transactions) {
+            match operation {
+                Operation::Set(key, value) => {
+                    assert_eq!(key.len(), 32);
+                    assert_eq!(value.len(), 100);
+                }
+                Operation::Get(key) | Operation::Remove(key) => assert_eq!(key.len(), 32),
+            }
+        }
+        assert_eq!(key(7, 4), "k007");
+    }
+}
@@ -0,0 +1,99 @@
+use clap::ValueEnum;
+
+use crate::distribution::KeyDistribution;
+use crate::workload::WorkloadConfig;
+
+/// Zipfian constant used by YCSB.
+const YCSB_THETA: f64 = 0.99;
+
+/// The YCSB core workloads, see
+/// <https://github.com/brianfrankcooper/YCSB/wiki/Core-Workloads>.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum YcsbWorkload {
+    /// Update heavy: 50% reads, 50% updates, zipfian keys.
+    A,
+    /// Read mostly: 95% reads, 5% updates, zipfian keys.
+    B,
+    /// Read only: 100% reads, zipfian keys.
+    C,
+    /// Read latest: 95% reads of recently inserted keys, 5% inserts.
+    D,
+    /// Short ranges: 95% scans, 5% inserts. Not supported, tenaciouszebra
+    /// tables have no range queries.
+    E,
+    /// Read-modify-write: 50% reads, 50% read-modify-writes, zipfian keys.
+    /// The write of a read-modify-write goes into the next transaction.
+    F,
+}
+
+impl YcsbWorkload {
+    pub fn name(self) -> &'static str {
+        match self {
+            YcsbWorkload::A => "a",
+            YcsbWorkload::B => "b",
+            YcsbWorkload::C => "c",
+            YcsbWorkload::D => "d",
+            YcsbWorkload::E => "e",
+            YcsbWorkload::F => "f",
+        }
+    }
+
+    /// Turns `base` into this workload's operation mix, keeping its sizes and
+    /// seed. Returns `None` for workloads heart cannot run.
+    pub fn apply(self, base: &WorkloadConfig) -> Option<WorkloadConfig> {
+        let zipfian = KeyDistribution::Zipfian { theta: YCSB_THETA };
+        let (write_percentage, read_modify_write_percentage, read_distribution, write_distribution) =
+            match self {
+                YcsbWorkload::A => (50, 0, zipfian, zipfian),
+                YcsbWorkload::B => (5, 0, zipfian, zipfian),
+                YcsbWorkload::C => (0, 0, zipfian, zipfian),
+                YcsbWorkload::D => (
+                    5,
+                    0,
+                    KeyDistribution::Latest { theta: YCSB_THETA },
+                    KeyDistribution::Sequential,
+                ),
+                YcsbWorkload::E => return None,
+                YcsbWorkload::F => (0, 50, zipfian, zipfian),
+            };
+
+        Some(WorkloadConfig {
+            write_percentage,
+            delete_percentage: 0,
+            read_modify_write_percentage,
+            read_distribution,
+            write_distribution,
+            ..base.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::workload::{Operation, Workload, DEFAULT_VALUE_SIZE};
+
+    #[test]
+    fn workloads_touch_every_key_once_per_transaction() {
+        let base = WorkloadConfig {
+            record_count: Some(200),
+            ..WorkloadConfig::inserts(42, 200, 10, DEFAULT_VALUE_SIZE)
+        };
+        for workload in YcsbWorkload::value_variants() {
+            let Some(config) = workload.apply(&base) else {
+                continue;
+            };
+            for operations in Workload::generate(&config).transactions {
+                let keys = operations
+                    .iter()
+                    .map(|operation| match operation {
+                        Operation::Set(key, _) | Operation::Get(key) | Operation::Remove(key) => key,
+                    })
+                    .collect::<HashSet<_>>();
+                assert_eq!(keys.len(), operations.len(), "workload {}", workload.name());
+            }
+        }
+    }
+}